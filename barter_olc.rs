@@ -1,4 +1,13 @@
 use scrypto::prelude::*;
+use crate::matching::{assert_nft_deposit_matches, nfts_per_collection, CollectionWant};
+use crate::events::*;
+use crate::fee_collector::ProtocolFee;
+use crate::royalties::{collection_royalty_shares, royalty_shares, RoyaltyShare, TradeSide};
+use crate::validation::{
+    assert_account, assert_blueprint, is_non_fungible_resource, validate_ask, validate_offered,
+    validate_proposal, TradeLimits,
+};
+use crate::vault_store::{ClaimSelection, VaultStore};
 
 // OVERVIEW OF BLUEPRINT
 
@@ -9,30 +18,53 @@ use scrypto::prelude::*;
 // USER FLOW
 
 // Alice creates a trade proposal by instantiating a component from this Blueprint. When instantiating the component
-// she deposits the assets she wants to trade into a hashmap of vaults in the component and sends a list of 
+// she deposits the assets she wants to trade into a store of vaults in the component and sends a list of 
 // of resource addresses and quanitities for the assets she would like to receive in return. She'll also receive an NFT badge
 // containing details of here trade proposal - this protects certain methods at a system level. 
 
-// Alice can then link her trading partner Bob to this trade with a short code generated on the front-end. 
-// This code is linked to the new component address that's been created on the TROVE back-end server.
+// Alice can then link her trading partner Bob to this trade with a short code. Every trade is created against a
+// TroveHub from this package - it registers itself there when it is created, gets its short code on-ledger,
+// resolvable by anyone through the hub, and keeps the hub's registry updated with every status change.
 
-// Bob can see what Alice has offered and what he needs to send in return. If he wants to take the trade,
+// Alice can ask for exact NFTs, or for any NFTs from a collection - optionally only from a set or range of ids,
+// or only those whose data matches field predicates such as a name or a minimum rarity.
+// The Escroceipt records which NFTs Bob actually delivered.
+
+// Bob can see what Alice has offered and what he needs to send in return, along with the revision of those
+// terms. Every deposit, accept, partial fill or counteroffer passes the revision Bob saw and fails if Alice has
+// amended the trade since. If he wants to take the trade,
 // he must deposit ALL of the assets specified in the trade proposal as they are compared against the list 
-// Alice provided earlier. If all the assets are present, they are deposited into another hashmap of vaults
+// Alice provided earlier. If all the assets are present, they are deposited into another store of vaults
 // within the component and the internal state of the component updates to allow him to withdraw Alice's assets.
+// His first deposit mints him a counterparty key - the rest of his deposit and his claim on Alice's assets
+// must present a proof of it, so nobody else watching the ledger can collect what he paid for.
 // Through the front-end and transaction manifest, this is all one transaction for Bob.
+// Alternatively Bob can call accept with all of his buckets at once, which checks them and hands him Alice's
+// assets in the same call.
 
 // Alice can then withdraw the assets that Bob has deposited.
 
+// The protocol fee is set by the fee collector the hub names - Alice can't choose another collector or leave the
+// fee out. It is read when the proposal is made and shown on the Escroceipt. Bob pays it on top of Alice's ask
+// and it is passed to the fee collector when the trade settles.
+
 // Alice also has a method to cancel the trade if it has not yet been completed in order to retrieve her
-// assets, if needed. 
+// assets, if needed. Until someone deposits, she can instead amend it - add or withdraw what she offers, or
+// change what she asks for - and keep the same component and short code.
+
+// When both sides are fungible, takers can fill any fraction of the trade and receive the same fraction of
+// Alice's tokens. The Escroceipt shows how much has been filled, Alice can collect the proceeds at any time and
+// cancelling returns only what is still unfilled.
+
+// Instead of taking Alice's terms, Bob can escrow a counteroffer. If Alice accepts it, it becomes the trade's
+// terms and Bob claims her assets as usual. If she rejects it, it is refunded to the account Bob named when he
+// made it. Until then Bob can change his mind and withdraw it.
 
 //=================================================================
 // To Do list:
-// 1. Integrate a fee collector - whether by v.0.10's royalty system or otherwise.
-// 2. Split out component instatiations for customisation - i.e. trade with a time limit or adding permitted accounts
-// 3. Add potential use of event emitters for notifying changes on the front-end
-// 4. Test and limit size of trade due to hashmap limits - avoid stuck assets in the component
+// 2. Split out component instatiations for customisation
+// 4. Record trade size figures with tests/bench.rs, before and after the vault store, and raise
+//    TradeLimits::CEILING to match
 // 5. Further checks for edge cases, possibilities of stuck resources...
 // 6. Optimisations for fees - i.e. badge minting and loop methods used
 
@@ -40,6 +72,7 @@ use scrypto::prelude::*;
 // A larger "to do", is to try to enforce NFT royalties as part of the system. The intention is not to create
 // a solution for avoiding creator royalties - however this will depend heavily upon the standard
 // that is adopted by the community, i.e. clearing house, transient tokens, etc. 
+// For now royalties are read from collection metadata and paid at settlement - see royalties.rs.
 
 
 //=================================================================
@@ -49,18 +82,41 @@ use scrypto::prelude::*;
 mod barter {
 
     struct Barter {
-        a_nft_vaults: HashMap<ResourceAddress, Vault>,
+        a_nft_vaults: VaultStore,
         a_vault_key: ResourceAddress,
         a_vault_key_id: NonFungibleLocalId,
-        b_nft_vaults: HashMap<ResourceAddress, Vault>,
+        b_nft_vaults: VaultStore,
+        b_key: ResourceAddress,
+        b_key_id: Option<NonFungibleLocalId>,
+        b_refund_account: Option<ComponentAddress>,
         badge_sweeper: Vault,
         receipt_addr: ResourceAddress,
         receipt_gaddr: NonFungibleLocalId,
         escrow_receipt: Vault,
         expected_nfts: Vec<NonFungibleGlobalId>,
+        b_nft_wants: Vec<CollectionWant>,
         expected_tokens: HashMap<ResourceAddress, Decimal>,
-        tokens_validated: bool,
-        nfts_validated: bool,
+        b_deposited_nfts: Vec<NonFungibleGlobalId>,
+        b_deposited_tokens: HashMap<ResourceAddress, Decimal>,
+        status: TradeStatus,
+        expiry: Option<Expiry>,
+        fee_collector: ComponentAddress,
+        fee_amounts: HashMap<ResourceAddress, Decimal>,
+        fee_vaults: VaultStore,
+        royalties: Vec<RoyaltyShare>,
+        limits: TradeLimits,
+        hub: ComponentAddress,
+        registrar_badge: Vault,
+        a_nfts: Vec<NonFungibleGlobalId>,
+        a_tokens: HashMap<ResourceAddress, Decimal>,
+        fee_terms: ProtocolFee,
+        counter_offers: KeyValueStore<u64, CounterOffer>,
+        counter_count: u64,
+        pending_counters: u32,
+        counter_vaults: KeyValueStore<u64, VaultStore>,
+        filled: Decimal,
+        revision: u64,
+        superseded_ask: Option<SupersededAsk>,
     }
 
     impl Barter {
@@ -70,23 +126,103 @@ mod barter {
             a_token_buckets: Vec<Bucket>,
             b_nft_deposits: Vec<NonFungibleGlobalId>,
             b_token_deposits: HashMap<ResourceAddress, Decimal>,
-        ) -> (ComponentAddress, Bucket) {
-         
+            options: TradeOptions,
+            hub: ComponentAddress,
+            creator: Option<Proof>,
+        ) -> (ComponentAddress, String, Bucket) {
+            let TradeOptions {
+                expiry,
+                permitted_takers,
+                limits,
+                collection_wants,
+            } = options;
+
+            // the hub decides the fee collector, so the fee can't be skipped by choosing a different one
+
+            assert_blueprint(hub, "TroveHub");
+            let hub_ref = borrow_component!(hub);
+            let fee_collector: ComponentAddress = hub_ref.call("fee_collector", scrypto_args!());
+            let registrar_resource: ResourceAddress =
+                hub_ref.call("registrar_badge", scrypto_args!());
+
+            // the trade is listed under the creator badge A presents, so all of A's trades can be found together
+
+            let creator: Option<NonFungibleGlobalId> = creator.map(|proof| {
+                let creator_badge: ResourceAddress = hub_ref.call("creator_badge", scrypto_args!());
+                let validated = proof
+                    .validate_proof(ProofValidationMode::ValidateResourceAddress(creator_badge))
+                    .expect("Proof is not a creator badge from this hub");
+                let creator =
+                    NonFungibleGlobalId::new(creator_badge, validated.non_fungible_local_id());
+                validated.drop();
+                creator
+            });
+
+            let limits = limits.unwrap_or_default();
+            limits.assert_within_ceiling();
+
+            // reject malformed proposals before anything is minted
+
+            validate_proposal(
+                &a_nft_buckets,
+                &a_token_buckets,
+                &b_nft_deposits,
+                &collection_wants,
+                &b_token_deposits,
+            );
+
+            if let Some(expiry) = &expiry {
+                assert!(!expiry.has_passed(), "Trade expiry must be in the future");
+            }
+
+            // every NFT in every bucket is recorded, whatever its NonFungibleData looks like
 
             let mut a_nft_deposits: Vec<NonFungibleGlobalId> = Vec::new();
 
             for bucket in a_nft_buckets.iter() {
-                let fucket: NonFungible<Escroceipt> = bucket.non_fungible();
-                let example: NonFungibleGlobalId = fucket.global_id().clone();
-                a_nft_deposits.push(example);
+                a_nft_deposits.extend(nft_global_ids(bucket));
             }
 
             let mut a_token_deposits: HashMap<ResourceAddress, Decimal> = HashMap::new();
 
             for i in a_token_buckets.iter() {
-                a_token_deposits.insert(i.resource_address(), i.amount());
+                *a_token_deposits
+                    .entry(i.resource_address())
+                    .or_insert(Decimal::zero()) += i.amount();
             }
 
+            // protocol fee terms are read once, so the cost of the trade is fixed from the moment it's proposed
+
+            let fee_terms: ProtocolFee =
+                borrow_component!(fee_collector).call("fee_terms", scrypto_args!());
+
+            let fee_amounts: HashMap<ResourceAddress, Decimal> =
+                fee_terms.amounts_for(&b_token_deposits);
+
+            // keep both sides small enough to be settled or cancelled in one transaction
+
+            limits.check_side("A", &a_nft_deposits, &a_token_deposits);
+            limits.check_wanted_side(
+                "B",
+                &b_nft_deposits,
+                &collection_wants,
+                &with_fees(&b_token_deposits, &fee_amounts),
+            );
+
+            // creator royalties on each side's NFTs, paid out of the fungibles offered against them
+
+            let mut royalties = royalty_shares(&a_nft_deposits, &b_token_deposits, TradeSide::B);
+            royalties.extend(collection_royalty_shares(
+                &nfts_per_collection(&b_nft_deposits, &collection_wants),
+                &a_token_deposits,
+                TradeSide::A,
+            ));
+
+            // the component's own record of A's side, kept for amendments and partial fills
+
+            let offered_nfts = a_nft_deposits.clone();
+            let offered_tokens = a_token_deposits.clone();
+
             let my_nfts = a_nft_deposits.clone();
             let my_tokens = a_token_deposits.clone();
 
@@ -129,7 +265,16 @@ mod barter {
                     a_token_contents: a_token_deposits,
                     b_nft_contents: b_nft_deposits,
                     b_token_contents: b_token_deposits,
-                    ready_to_claim: false,
+                    b_nft_wants: collection_wants.clone(),
+                    b_nfts_delivered: Vec::new(),
+                    status: TradeStatus::Open,
+                    expiry: expiry.clone(),
+                    permitted_takers: permitted_takers.clone(),
+                    fee_terms: fee_terms.clone(),
+                    fee_amounts: fee_amounts.clone(),
+                    royalties: royalties.clone(),
+                    filled_percentage: Decimal::zero(),
+                    revision: 0,
                 }]);
             //
 
@@ -153,213 +298,1704 @@ mod barter {
                     a_token_contents: my_tokens,
                     b_nft_contents: nfts_i_expect,
                     b_token_contents: tokens_i_expect,
-                    ready_to_claim: false,
+                    b_nft_wants: collection_wants.clone(),
+                    b_nfts_delivered: Vec::new(),
+                    status: TradeStatus::Open,
+                    expiry: expiry.clone(),
+                    permitted_takers: permitted_takers.clone(),
+                    fee_terms: fee_terms.clone(),
+                    fee_amounts: fee_amounts.clone(),
+                    royalties: royalties.clone(),
+                    filled_percentage: Decimal::zero(),
+                    revision: 0,
                 }]);
             
 
-            // Deposit user A assets into hashmap of vaults
+            // Key minted to User B on their first deposit, binding the claim on A's assets to them
+
+            let b_key = ResourceBuilder::new_uuid_non_fungible::<CounterpartyKey>()
+                .metadata("name", &key_custom_name)
+                .metadata("description", "Your side of a trade on trove.eco")
+                .mintable(rule!(require(badge_sweeper.resource_address())), LOCKED)
+                .burnable(rule!(require(badge_sweeper.resource_address())), LOCKED)
+                .create_with_no_initial_supply();
+
+            // Deposit user A assets into the vault store
 
-            let mut user_a_vaults = HashMap::new();
+            let mut user_a_vaults = VaultStore::new();
 
             for bucket in a_nft_buckets.into_iter() {
-                user_a_vaults
-                    .entry(bucket.resource_address())
-                    .or_insert(Vault::new(bucket.resource_address()))
-                    .put(bucket)
+                user_a_vaults.put(bucket)
             }
 
             for bucket in a_token_buckets.into_iter() {
-                user_a_vaults
-                    .entry(bucket.resource_address())
-                    .or_insert(Vault::new(bucket.resource_address()))
-                    .put(bucket)
+                user_a_vaults.put(bucket)
             }
 
-            //
+            // B's methods are open to anyone unless A named who may take the trade
+
+            let taker_rule = taker_access_rule(&permitted_takers);
 
             let rules = AccessRulesConfig::new()
                 .method("cancel", rule!(require(a_key.resource_address())), LOCKED)
+                .method(
+                    "cancel_partial",
+                    rule!(require(a_key.resource_address())),
+                    LOCKED,
+                )
                 .method(
                     "claim_my_new_assets",
                     rule!(require(a_key.resource_address())),
                     LOCKED,
                 )
+                .method(
+                    "claim_my_new_assets_partial",
+                    rule!(require(a_key.resource_address())),
+                    LOCKED,
+                )
+                .method(
+                    "accept_counter",
+                    rule!(require(a_key.resource_address())),
+                    LOCKED,
+                )
+                .method(
+                    "reject_counter",
+                    rule!(require(a_key.resource_address())),
+                    LOCKED,
+                )
+                .method(
+                    "claim_proceeds",
+                    rule!(require(a_key.resource_address())),
+                    LOCKED,
+                )
+                .method(
+                    "add_offered_assets",
+                    rule!(require(a_key.resource_address())),
+                    LOCKED,
+                )
+                .method(
+                    "withdraw_offered_assets",
+                    rule!(require(a_key.resource_address())),
+                    LOCKED,
+                )
+                .method(
+                    "amend_ask",
+                    rule!(require(a_key.resource_address())),
+                    LOCKED,
+                )
+                .method("b_deposit_nfts", taker_rule.clone(), LOCKED)
+                .method("b_deposit_tokens", taker_rule.clone(), LOCKED)
+                .method("b_claims_a_assets", taker_rule.clone(), LOCKED)
+                .method("b_claims_a_assets_partial", taker_rule.clone(), LOCKED)
+                .method("accept", taker_rule.clone(), LOCKED)
+                .method("counter_offer", taker_rule.clone(), LOCKED)
+                .method("fill_partial", taker_rule, LOCKED)
                 .default(AccessRule::AllowAll, AccessRule::DenyAll);
 
+            // the creation event is read back from the escrow receipt rather than from another set of copies
+
+            let created: Escroceipt = escrow_record.non_fungible::<Escroceipt>().data();
+            let (offered, wanted) = listed_resources(
+                &created.a_nft_contents,
+                &created.a_token_contents,
+                &created.b_nft_contents,
+                &created.b_nft_wants,
+                &created.b_token_contents,
+            );
+
             let component = Self {
                 a_vault_key: a_key.resource_address(),
                 a_vault_key_id: a_key.non_fungible_local_id(),
                 a_nft_vaults: user_a_vaults,
-                b_nft_vaults: HashMap::new(),
+                b_nft_vaults: VaultStore::new(),
+                b_key,
+                b_key_id: None,
+                b_refund_account: None,
                 badge_sweeper: Vault::with_bucket(badge_sweeper),
                 expected_nfts: expected_b_nft_deposits,
+                b_nft_wants: collection_wants,
                 expected_tokens: expected_b_token_deposits,
                 receipt_addr: escrow_record.resource_address(),
                 receipt_gaddr: escrow_record.non_fungible_local_id(),
                 escrow_receipt: Vault::with_bucket(escrow_record),
-                tokens_validated: false,
-                nfts_validated: false,
+                b_deposited_nfts: Vec::new(),
+                b_deposited_tokens: HashMap::new(),
+                status: TradeStatus::Open,
+                expiry,
+                fee_collector,
+                fee_amounts,
+                fee_vaults: VaultStore::new(),
+                royalties,
+                limits,
+                hub,
+                registrar_badge: Vault::new(registrar_resource),
+                a_nfts: offered_nfts,
+                a_tokens: offered_tokens,
+                fee_terms,
+                counter_offers: KeyValueStore::new(),
+                counter_count: 0,
+                pending_counters: 0,
+                counter_vaults: KeyValueStore::new(),
+                filled: Decimal::zero(),
+                revision: 0,
+                superseded_ask: None,
             }
             .instantiate();
             let component_address = component.globalize_with_access_rules(rules);
 
+            Runtime::emit_event(ProposalCreatedEvent {
+                component: component_address,
+                a_key_id: a_key.non_fungible_local_id(),
+                a_nfts: created.a_nft_contents,
+                a_tokens: created.a_token_contents,
+                b_nfts: created.b_nft_contents,
+                b_wants: created.b_nft_wants,
+                b_tokens: created.b_token_contents,
+            });
+
+            // the hub lists the trade and issues the registrar badge it reports back with
+
+            let (short_code, registrar): (String, Bucket) = borrow_component!(hub).call(
+                "register_trade",
+                scrypto_args!(component_address, creator, offered, wanted),
+            );
+            borrow_component!(component_address)
+                .call::<()>("attach_registrar", scrypto_args!(registrar));
+
             // return badge to a
-            (component_address, a_key)
+            (component_address, short_code, a_key)
+        }
+
+        // Called once by the constructor with the badge the hub issued on registration. The vault only takes
+        // the hub's registrar badge, and only while it is empty.
+
+        pub fn attach_registrar(&mut self, registrar: Bucket) {
+            assert!(
+                self.registrar_badge.is_empty(),
+                "This trade already holds its registrar badge"
+            );
+            self.registrar_badge.put(registrar);
+        }
+
+        pub fn hub(&self) -> ComponentAddress {
+            self.hub
         }
 
 
 // Separated methods for depositing either tokens or NFTs as the details are treated differently.
 // This makes it so it's not mandatory to include both NFTs and tokens in a trade.
 // Inclusion of each method can be determined on the front-end.
+// The first deposit returns B's counterparty key, the second must be made with a proof of it.
+// Once every part of B's side is in, the trade moves from Open to Filled.
+// If A cancels before B has been paid, B's deposit is sent back to the refund account given with it.
+
+        pub fn b_deposit_nfts(
+            &mut self,
+            b_nft_assets: Vec<Bucket>,
+            b_key: Option<Proof>,
+            refund_account: ComponentAddress,
+            revision: u64,
+        ) -> Option<Bucket> {
+            self.require_status(&[TradeStatus::Open], "deposit NFTs");
+            self.assert_not_expired();
+            self.assert_revision(revision);
+            assert!(self.asks_for_nfts(), "This trade doesn't ask for any NFTs");
+            assert!(
+                self.b_deposited_nfts.is_empty(),
+                "NFTs have already been deposited for this trade"
+            );
 
-        pub fn b_deposit_nfts(&mut self, b_nft_assets: Vec<Bucket>) {
-            let mut nft_record: Vec<NonFungibleGlobalId> = Vec::new();
+            let nft_record = self.validate_nft_deposit(&b_nft_assets);
+
+            let new_key = self.bind_counterparty(b_key, refund_account);
+
+            self.store_b_assets(b_nft_assets);
+            self.b_deposited_nfts = nft_record.clone();
+            self.update_receipts("b_nfts_delivered", nft_record.clone());
 
-            for i in &b_nft_assets {
-                let nft_resource = i.resource_address();
-                let nft_id = i.non_fungible_local_id();
-                let nft_global = NonFungibleGlobalId::new(nft_resource, nft_id);
-                nft_record.push(nft_global)
+            Runtime::emit_event(CounterpartyDepositEvent {
+                component: Runtime::global_address(),
+                a_key_id: self.a_vault_key_id.clone(),
+                nfts: nft_record,
+                tokens: HashMap::new(),
+            });
+
+            if self.b_side_complete() {
+                self.set_status(TradeStatus::Filled);
             }
 
-            let sorted_record = nft_record.sort();
-            let sorted_criteria = self.expected_nfts.sort();
+            new_key
+        }
 
+        // Token deposits may cover the ask with more than is needed - the exact ask and any protocol fee are
+        // kept and the excess is returned as change alongside the counterparty key.
+
+        pub fn b_deposit_tokens(
+            &mut self,
+            b_token_assets: Vec<Bucket>,
+            b_key: Option<Proof>,
+            refund_account: ComponentAddress,
+            revision: u64,
+        ) -> (Option<Bucket>, Vec<Bucket>) {
+            self.require_status(&[TradeStatus::Open], "deposit tokens");
+            self.assert_not_expired();
+            self.assert_revision(revision);
+            self.assert_not_partially_filled();
+            assert!(
+                !self.required_tokens().is_empty(),
+                "This trade doesn't ask for any tokens"
+            );
             assert!(
-                sorted_record == sorted_criteria,
-                "Deposit does not meet A's expectations"
+                self.b_deposited_tokens.is_empty(),
+                "Tokens have already been deposited for this trade"
             );
 
-            for bucket in b_nft_assets.into_iter() {
-                self.b_nft_vaults
-                    .entry(bucket.resource_address())
-                    .or_insert(Vault::new(bucket.resource_address()))
-                    .put(bucket)
+            let (payment, fees, change) = self.take_token_payment(
+                b_token_assets,
+                &self.expected_tokens,
+                &self.fee_amounts,
+            );
+
+            let new_key = self.bind_counterparty(b_key, refund_account);
+
+            self.store_b_assets(payment);
+            self.store_fees(fees);
+            self.b_deposited_tokens = self.required_tokens();
+
+            Runtime::emit_event(CounterpartyDepositEvent {
+                component: Runtime::global_address(),
+                a_key_id: self.a_vault_key_id.clone(),
+                nfts: Vec::new(),
+                tokens: self.b_deposited_tokens.clone(),
+            });
+
+            if self.b_side_complete() {
+                self.set_status(TradeStatus::Filled);
             }
 
-            self.nfts_validated = true
+            (new_key, change)
         }
 
-        pub fn b_deposit_tokens(&mut self, b_token_assets: Vec<Bucket>) {
-            let token_criteria = self.expected_tokens.clone();
-            let mut b_deposit_hm: HashMap<ResourceAddress, Decimal> = HashMap::new();
-            for bucket in b_token_assets.iter() {
-                b_deposit_hm.insert(bucket.resource_address(), bucket.amount());
+        pub fn b_claims_a_assets(&mut self, b_key: Proof) -> Vec<Bucket> {
+            self.b_claim(b_key, None)
+        }
+
+        // Chunked versions of the claim and cancel methods, for trades too large to empty in one transaction.
+        // Each call takes the given resources, or the first N still held, and unclaimed_assets shows what is left.
+        // The first chunk settles or cancels the trade, later chunks just collect the rest.
+
+        pub fn b_claims_a_assets_partial(
+            &mut self,
+            b_key: Proof,
+            selection: ClaimSelection,
+        ) -> Vec<Bucket> {
+            self.b_claim(b_key, Some(selection))
+        }
+
+        pub fn claim_my_new_assets_partial(&mut self, selection: ClaimSelection) -> Vec<Bucket> {
+            self.a_claim(Some(selection))
+        }
+
+        pub fn cancel_partial(&mut self, selection: ClaimSelection) -> Vec<Bucket> {
+            self.a_withdraw(Some(selection))
+        }
+
+        // resources still held for A's side and for B's side
+
+        pub fn unclaimed_assets(&self) -> (Vec<ResourceAddress>, Vec<ResourceAddress>) {
+            (
+                self.a_nft_vaults.resources().to_vec(),
+                self.b_nft_vaults.resources().to_vec(),
+            )
+        }
+
+        // Single step alternative to the methods above - B hands over every bucket for their side of the
+        // trade and receives A's assets in the same call. Fungible and non-fungible buckets are sorted here
+        // so the front-end can pass the whole lot in one list. Nothing is left partially filled and no
+        // counterparty key is needed.
+
+        pub fn accept(&mut self, b_assets: Vec<Bucket>, revision: u64) -> Vec<Bucket> {
+            self.require_status(&[TradeStatus::Open], "accept");
+            self.assert_not_expired();
+            self.assert_revision(revision);
+            self.assert_not_partially_filled();
+            assert!(
+                self.b_key_id.is_none(),
+                "Another counterparty is part way through depositing for this trade"
+            );
+
+            let (b_nft_assets, b_token_assets): (Vec<Bucket>, Vec<Bucket>) =
+                b_assets.into_iter().partition(is_non_fungible);
+
+            let nft_record = self.validate_nft_deposit(&b_nft_assets);
+            let (payment, fees, change) = self.take_token_payment(
+                b_token_assets,
+                &self.expected_tokens,
+                &self.fee_amounts,
+            );
+
+            self.store_b_assets(b_nft_assets);
+            self.store_b_assets(payment);
+            self.store_fees(fees);
+            self.b_deposited_nfts = nft_record.clone();
+            self.b_deposited_tokens = self.required_tokens();
+            self.update_receipts("b_nfts_delivered", nft_record.clone());
+
+            Runtime::emit_event(CounterpartyDepositEvent {
+                component: Runtime::global_address(),
+                a_key_id: self.a_vault_key_id.clone(),
+                nfts: nft_record,
+                tokens: self.b_deposited_tokens.clone(),
+            });
+
+            self.set_status(TradeStatus::Settled);
+            self.pay_fees();
+            self.pay_royalties();
+
+            let mut buckets = self.a_nft_vaults.take_all();
+            self.emit_claimed_by_b(&buckets);
+            buckets.extend(change);
+
+            buckets
+        }
+
+        // Whatever B has deposited but not yet been paid for is sent back to B's refund account when A cancels.
+        // Cancelling after the expiry closes the trade as Expired rather than Cancelled.
+
+        pub fn cancel(&mut self) -> Vec<Bucket> {
+            self.a_withdraw(None)
+        }
+
+        pub fn claim_my_new_assets(&mut self) -> Vec<Bucket> {
+            self.a_claim(None)
+        }
+
+        // Lets B take back a partial deposit they don't want to complete, or a complete one A hasn't been paid
+        // for yet. The trade reopens for other counterparties.
+
+        pub fn b_withdraw_deposit(&mut self, b_key: Proof) -> Vec<Bucket> {
+            self.require_status(
+                &[TradeStatus::Open, TradeStatus::Filled],
+                "withdraw a deposit",
+            );
+            self.check_counterparty(b_key);
+
+            let reopening = self.status == TradeStatus::Filled;
+            let buckets = self.release_b_deposit(reopening);
+
+            if reopening {
+                self.set_status(TradeStatus::Open);
             }
 
-            assert!(b_deposit_hm == token_criteria, "Token deposits don't match");
+            buckets
+        }
 
-            for bucket in b_token_assets.into_iter() {
-                self.b_nft_vaults
-                    .entry(bucket.resource_address())
-                    .or_insert(Vault::new(bucket.resource_address()))
-                    .put(bucket)
+        // B proposes different terms for A's side. Every NFT in the buckets is offered, along with the token
+        // amounts named in b_token_offer - the tokens must also cover the protocol fee on that offer, and any
+        // excess comes back as change. The returned key is needed to withdraw the counteroffer, or to claim
+        // A's assets if A accepts it. If A rejects it, everything escrowed is sent to refund_account.
+
+        pub fn counter_offer(
+            &mut self,
+            b_assets: Vec<Bucket>,
+            b_token_offer: HashMap<ResourceAddress, Decimal>,
+            refund_account: ComponentAddress,
+            revision: u64,
+        ) -> (Bucket, Vec<Bucket>) {
+            self.require_status(&[TradeStatus::Open], "make a counteroffer");
+            self.assert_not_expired();
+            self.assert_revision(revision);
+            assert_account(refund_account);
+            assert!(
+                self.pending_counters < MAX_PENDING_COUNTERS,
+                "This trade already has {} pending counteroffers",
+                MAX_PENDING_COUNTERS
+            );
+
+            for (resource_address, amount) in b_token_offer.iter() {
+                assert!(
+                    *amount > Decimal::zero(),
+                    "Offered amount of {:?} must be greater than zero",
+                    resource_address
+                );
+            }
+
+            let (b_nft_assets, b_token_assets): (Vec<Bucket>, Vec<Bucket>) =
+                b_assets.into_iter().partition(is_non_fungible);
+
+            let mut nfts: Vec<NonFungibleGlobalId> = Vec::new();
+            for bucket in b_nft_assets.iter() {
+                nfts.extend(nft_global_ids(bucket));
+            }
+            assert!(
+                !nfts.is_empty() || !b_token_offer.is_empty(),
+                "Counteroffer is empty"
+            );
+
+            let fee_amounts = self.fee_terms.amounts_for(&b_token_offer);
+
+            self.limits
+                .check_side("B", &nfts, &with_fees(&b_token_offer, &fee_amounts));
+
+            let (payment, fees, change) =
+                self.take_token_payment(b_token_assets, &b_token_offer, &fee_amounts);
+
+            let mut escrow = VaultStore::new();
+            for bucket in b_nft_assets.into_iter().chain(payment).chain(fees) {
+                escrow.put(bucket);
+            }
+
+            let key: Bucket = self.badge_sweeper.authorize(|| {
+                borrow_resource_manager!(self.b_key).mint_uuid_non_fungible(CounterpartyKey {})
+            });
+
+            let id = self.counter_count;
+            self.counter_count += 1;
+            self.pending_counters += 1;
+
+            Runtime::emit_event(CounterOfferMadeEvent {
+                component: Runtime::global_address(),
+                a_key_id: self.a_vault_key_id.clone(),
+                counter_id: id,
+                nfts: nfts.clone(),
+                tokens: with_fees(&b_token_offer, &fee_amounts),
+            });
+
+            self.counter_vaults.insert(id, escrow);
+            self.counter_offers.insert(
+                id,
+                CounterOffer {
+                    id,
+                    key_id: key.non_fungible_local_id(),
+                    refund_account,
+                    nfts,
+                    tokens: b_token_offer,
+                    fee_amounts,
+                    status: CounterStatus::Pending,
+                },
+            );
+
+            (key, change)
+        }
+
+        // Accepting a counteroffer makes its terms the trade's terms and its maker the counterparty. The trade
+        // is Filled, and B claims A's assets with their counteroffer key as usual.
+
+        pub fn accept_counter(&mut self, counter_id: u64) {
+            self.require_status(&[TradeStatus::Open], "accept a counteroffer");
+            self.assert_not_expired();
+            self.assert_not_partially_filled();
+            assert!(
+                self.b_key_id.is_none(),
+                "Another counterparty is part way through depositing for this trade"
+            );
+
+            let counter = self.pending_counter(counter_id);
+
+            // fees go to the fee vaults, everything else becomes B's deposit
+
+            let mut escrow = self.counter_vaults.get_mut(&counter_id).unwrap();
+            for (resource_address, fee) in counter.fee_amounts.iter() {
+                self.fee_vaults.put(escrow.take(*resource_address, *fee));
+            }
+            for bucket in escrow.take_all().into_iter() {
+                self.b_nft_vaults.put(bucket);
+            }
+            drop(escrow);
+
+            self.set_counter_status(counter_id, CounterStatus::Accepted);
+
+            // A's own ask is kept so it can be put back if B withdraws, and the switch to the counteroffer's
+            // terms goes through record_amendment like any other change of terms
+
+            self.superseded_ask = Some(SupersededAsk {
+                counter_id,
+                nfts: self.expected_nfts.clone(),
+                wants: self.b_nft_wants.clone(),
+                tokens: self.expected_tokens.clone(),
+            });
+
+            self.expected_nfts = counter.nfts.clone();
+            self.b_nft_wants = Vec::new();
+            self.expected_tokens = counter.tokens.clone();
+            self.record_amendment();
+
+            self.b_key_id = Some(counter.key_id.clone());
+            self.b_refund_account = Some(counter.refund_account);
+            self.b_deposited_nfts = counter.nfts.clone();
+            self.b_deposited_tokens = self.required_tokens();
+            self.update_receipts("b_nfts_delivered", counter.nfts.clone());
+
+            Runtime::emit_event(CounterpartyDepositEvent {
+                component: Runtime::global_address(),
+                a_key_id: self.a_vault_key_id.clone(),
+                nfts: counter.nfts,
+                tokens: self.b_deposited_tokens.clone(),
+            });
+
+            self.set_status(TradeStatus::Filled);
+        }
+
+        // a rejected counteroffer is refunded straight to the account its maker named
+
+        pub fn reject_counter(&mut self, counter_id: u64) {
+            self.require_status(
+                &[TradeStatus::Open, TradeStatus::Filled],
+                "reject a counteroffer",
+            );
+            let counter = self.pending_counter(counter_id);
+
+            self.set_counter_status(counter_id, CounterStatus::Rejected);
+
+            let refund = self
+                .counter_vaults
+                .get_mut(&counter_id)
+                .unwrap()
+                .take_all();
+            borrow_component!(counter.refund_account)
+                .call::<()>("deposit_batch", scrypto_args!(refund));
+
+            Runtime::emit_event(CounterOfferRejectedEvent {
+                component: Runtime::global_address(),
+                a_key_id: self.a_vault_key_id.clone(),
+                counter_id,
+                refund_account: counter.refund_account,
+            });
+        }
+
+        // B can take back a pending counteroffer at any time
+
+        pub fn withdraw_counter(&mut self, counter_id: u64, counter_key: Proof) -> Vec<Bucket> {
+            let counter = self.pending_counter(counter_id);
+
+            let validated = counter_key
+                .validate_proof(ProofValidationMode::ValidateContainsNonFungible(
+                    NonFungibleGlobalId::new(self.b_key, counter.key_id),
+                ))
+                .expect("Proof is not the key for this counteroffer");
+            validated.drop();
+
+            self.set_counter_status(counter_id, CounterStatus::Withdrawn);
+
+            Runtime::emit_event(CounterOfferWithdrawnEvent {
+                component: Runtime::global_address(),
+                a_key_id: self.a_vault_key_id.clone(),
+                counter_id,
+            });
+
+            self.counter_vaults
+                .get_mut(&counter_id)
+                .unwrap()
+                .take_all()
+        }
+
+        // counteroffers are numbered from 0 in the order they were made, up to counter_count
+
+        pub fn counter_offer(&self, counter_id: u64) -> Option<CounterOffer> {
+            self.counter_offers.get(&counter_id).map(|counter| counter.clone())
+        }
+
+        pub fn counter_count(&self) -> u64 {
+            self.counter_count
+        }
+
+        // PARTIAL FILLS
+
+        // A trade with only fungibles on both sides can be filled a fraction at a time - a taker pays that
+        // fraction of the ask, plus the same fraction of the protocol fee, and receives that fraction of A's
+        // tokens straight away. What a taker pays is rounded up and what they receive is rounded down to each
+        // token's divisibility, and the fill that completes the trade takes whatever is left on A's side. A fill
+        // that would round down to nothing of one of A's tokens is rejected.
+        // fraction is a share of the whole trade, e.g. dec!("0.25"), and can't be more than what is unfilled.
+
+        pub fn fill_partial(
+            &mut self,
+            b_token_assets: Vec<Bucket>,
+            fraction: Decimal,
+            revision: u64,
+        ) -> Vec<Bucket> {
+            self.require_status(&[TradeStatus::Open], "fill part of the trade");
+            self.assert_not_expired();
+            self.assert_revision(revision);
+            assert!(
+                self.a_nfts.is_empty() && !self.asks_for_nfts(),
+                "Only trades with fungibles on both sides can be partially filled"
+            );
+            assert!(
+                !self.a_tokens.is_empty() && !self.expected_tokens.is_empty(),
+                "Only trades with fungibles on both sides can be partially filled"
+            );
+            assert!(
+                self.b_key_id.is_none(),
+                "Another counterparty is part way through depositing for this trade"
+            );
+
+            let unfilled = Decimal::one() - self.filled;
+            assert!(
+                fraction > Decimal::zero() && fraction <= unfilled,
+                "Fraction must be greater than zero and at most the unfilled {}",
+                unfilled
+            );
+
+            let completes = fraction == unfilled;
+
+            let ask = scale_amounts(
+                &self.expected_tokens,
+                fraction,
+                RoundingMode::TowardsPositiveInfinity,
+            );
+            let fees = scale_amounts(
+                &self.fee_amounts,
+                fraction,
+                RoundingMode::TowardsPositiveInfinity,
+            );
+
+            // a fill too small to be owed any of A's tokens once rounded down would pay for nothing
+
+            let share = if completes {
+                None
+            } else {
+                let share = scale_amounts(&self.a_tokens, fraction, RoundingMode::TowardsZero);
+                for (resource_address, amount) in share.iter() {
+                    assert!(
+                        *amount > Decimal::zero(),
+                        "Fraction {} is too small to receive any {:?}",
+                        fraction,
+                        resource_address
+                    );
+                }
+                Some(share)
+            };
+
+            let (payment, fee_buckets, change) =
+                self.take_token_payment(b_token_assets, &ask, &fees);
+
+            // payment is kept for A to claim, fees go straight to the collector
+
+            self.store_b_assets(payment);
+            self.store_fees(fee_buckets);
+            self.pay_fees();
+
+            let mut buckets = match share {
+                None => self.a_nft_vaults.take_all(),
+                Some(share) => share
+                    .iter()
+                    .map(|(resource_address, amount)| {
+                        self.a_nft_vaults.take(*resource_address, *amount)
+                    })
+                    .collect(),
+            };
+            let (_, received) = asset_lists(&buckets);
+
+            self.filled += fraction;
+            self.update_receipts("filled_percentage", self.filled * dec!("100"));
+
+            Runtime::emit_event(PartialFillEvent {
+                component: Runtime::global_address(),
+                a_key_id: self.a_vault_key_id.clone(),
+                fraction,
+                filled: self.filled,
+                paid: with_fees(&ask, &fees),
+                received,
+            });
+
+            if completes {
+                self.set_status(TradeStatus::Settled);
             }
 
-            self.tokens_validated = true
+            buckets.extend(change);
+            buckets
         }
 
-        pub fn b_claims_a_assets(&mut self) -> Vec<Bucket> {
+        // A can collect what partial fills have paid at any time, including after cancelling the unfilled
+        // remainder. cancel only ever returns what hasn't been filled.
+
+        pub fn claim_proceeds(&mut self) -> Vec<Bucket> {
+            // a partially filled trade can't be Filled - B's whole-trade deposits are refused once it has been
+            self.require_status(
+                &[
+                    TradeStatus::Open,
+                    TradeStatus::Settled,
+                    TradeStatus::Cancelled,
+                    TradeStatus::Expired,
+                ],
+                "claim partial fill proceeds",
+            );
+            assert!(
+                self.filled > Decimal::zero(),
+                "This trade hasn't been partially filled"
+            );
+            assert!(
+                !self.b_nft_vaults.is_empty(),
+                "Proceeds have already been claimed"
+            );
+
+            let buckets = self.b_nft_vaults.take_all();
+            let (nfts, tokens) = asset_lists(&buckets);
 
-            // provide optionality for trades to include either tokens, nfts or both tokens and nfts.
+            Runtime::emit_event(ClaimedByAEvent {
+                component: Runtime::global_address(),
+                a_key_id: self.a_vault_key_id.clone(),
+                nfts,
+                tokens,
+            });
+
+            buckets
+        }
 
-            if !self.expected_nfts.len() > 0 {
+        pub fn filled(&self) -> Decimal {
+            self.filled
+        }
+
+        // AMENDMENTS
+
+        // While the trade is open and nobody has deposited into it or partially filled it, A can change what
+        // they offer and what they ask for without cancelling - the component, and its short code, stay the
+        // same. Each amendment bumps the revision on both Escroceipts. Fees and royalties are worked out again
+        // for the new terms.
+
+        pub fn add_offered_assets(
+            &mut self,
+            a_nft_buckets: Vec<Bucket>,
+            a_token_buckets: Vec<Bucket>,
+        ) {
+            self.assert_amendable();
+
+            let added = validate_offered(&a_nft_buckets, &a_token_buckets);
+            assert!(!added.is_empty(), "No assets to add");
+            validate_ask(
+                &added,
+                &self.expected_nfts,
+                &self.b_nft_wants,
+                &self.expected_tokens,
+            );
+
+            for bucket in a_nft_buckets.into_iter() {
+                self.a_nfts.extend(nft_global_ids(&bucket));
+                self.a_nft_vaults.put(bucket);
+            }
+            for bucket in a_token_buckets.into_iter() {
+                *self
+                    .a_tokens
+                    .entry(bucket.resource_address())
+                    .or_insert(Decimal::zero()) += bucket.amount();
+                self.a_nft_vaults.put(bucket);
+            }
+
+            self.record_amendment();
+        }
+
+        pub fn withdraw_offered_assets(
+            &mut self,
+            nfts: Vec<NonFungibleGlobalId>,
+            tokens: HashMap<ResourceAddress, Decimal>,
+        ) -> Vec<Bucket> {
+            self.assert_amendable();
+            assert!(
+                !nfts.is_empty() || !tokens.is_empty(),
+                "No assets to withdraw"
+            );
+
+            let mut per_collection: BTreeMap<ResourceAddress, BTreeSet<NonFungibleLocalId>> =
+                BTreeMap::new();
+            for nft in nfts.iter() {
+                assert!(self.a_nfts.contains(nft), "NFT {:?} is not on offer", nft);
                 assert!(
-                    self.nfts_validated,
-                    "Insufficient assets deposited for trade"
-                )
+                    per_collection
+                        .entry(nft.resource_address())
+                        .or_insert_with(BTreeSet::new)
+                        .insert(nft.local_id().clone()),
+                    "NFT {:?} is listed more than once",
+                    nft
+                );
             }
-            if !self.expected_tokens.is_empty() {
+
+            for (resource_address, amount) in tokens.iter() {
+                let offered = self
+                    .a_tokens
+                    .get(resource_address)
+                    .cloned()
+                    .unwrap_or(Decimal::zero());
                 assert!(
-                    self.tokens_validated,
-                    "Insufficient assets deposited for trade"
-                )
+                    *amount > Decimal::zero() && *amount <= offered,
+                    "Can't withdraw {} of {:?}, {} is on offer",
+                    amount,
+                    resource_address,
+                    offered
+                );
             }
 
-            let a_assets: Vec<ResourceAddress> = self.a_nft_vaults.keys().cloned().collect();
+            let mut buckets: Vec<Bucket> = Vec::new();
+
+            for (resource_address, ids) in per_collection.iter() {
+                buckets.push(self.a_nft_vaults.take_non_fungibles(*resource_address, ids));
+            }
+            self.a_nfts.retain(|nft| !nfts.contains(nft));
 
-            let mut buckets: Vec<Bucket> = vec![];
+            for (resource_address, amount) in tokens.iter() {
+                buckets.push(self.a_nft_vaults.take(*resource_address, *amount));
 
-            for resource_address in a_assets.into_iter() {
-                buckets.push(
-                    self.a_nft_vaults
-                        .get_mut(&resource_address)
-                        .unwrap()
-                        .take_all(),
-                )
+                let remaining = self.a_tokens[resource_address] - *amount;
+                if remaining.is_zero() {
+                    self.a_tokens.remove(resource_address);
+                } else {
+                    self.a_tokens.insert(*resource_address, remaining);
+                }
             }
 
-            self.badge_sweeper.authorize(|| {
-                borrow_resource_manager!(self.receipt_addr).update_non_fungible_data(
-                    &self.receipt_gaddr,
-                    "ready_to_claim",
-                    true,
-                )
+            self.record_amendment();
+
+            buckets
+        }
+
+        // replaces the whole ask - pass the current value of anything that shouldn't change
+
+        pub fn amend_ask(
+            &mut self,
+            b_nft_deposits: Vec<NonFungibleGlobalId>,
+            collection_wants: Vec<CollectionWant>,
+            b_token_deposits: HashMap<ResourceAddress, Decimal>,
+        ) {
+            self.assert_amendable();
+
+            let mut offered: HashSet<ResourceAddress> =
+                self.a_nfts.iter().map(|nft| nft.resource_address()).collect();
+            offered.extend(self.a_tokens.keys().cloned());
+
+            validate_ask(&offered, &b_nft_deposits, &collection_wants, &b_token_deposits);
+
+            self.expected_nfts = b_nft_deposits;
+            self.b_nft_wants = collection_wants;
+            self.expected_tokens = b_token_deposits;
+
+            self.record_amendment();
+        }
+
+        pub fn revision(&self) -> u64 {
+            self.revision
+        }
+
+        pub fn status(&self) -> TradeStatus {
+            self.status
+        }
+
+        // after accepted
+
+        // B receives a counterparty key with their first deposit. Any further deposit must be made with a proof
+        // of that same key, so a trade can only ever have one counterparty.
+
+        fn bind_counterparty(
+            &mut self,
+            b_key: Option<Proof>,
+            refund_account: ComponentAddress,
+        ) -> Option<Bucket> {
+            assert_account(refund_account);
+            self.b_refund_account = Some(refund_account);
+
+            match b_key {
+                None => {
+                    assert!(
+                        self.b_key_id.is_none(),
+                        "A counterparty has already deposited, their key is required to continue"
+                    );
+
+                    let key: Bucket = self.badge_sweeper.authorize(|| {
+                        borrow_resource_manager!(self.b_key).mint_uuid_non_fungible(CounterpartyKey {})
+                    });
+                    self.b_key_id = Some(key.non_fungible_local_id());
+
+                    Some(key)
+                }
+                Some(proof) => {
+                    self.check_counterparty(proof);
+                    None
+                }
+            }
+        }
+
+        fn check_counterparty(&self, b_key: Proof) {
+            let key_id = self
+                .b_key_id
+                .clone()
+                .expect("No counterparty has deposited into this trade");
+
+            let validated = b_key
+                .validate_proof(ProofValidationMode::ValidateContainsNonFungible(
+                    NonFungibleGlobalId::new(self.b_key, key_id),
+                ))
+                .expect("Proof is not the counterparty key for this trade");
+            validated.drop();
+        }
+
+        fn validate_nft_deposit(&self, b_nft_assets: &[Bucket]) -> Vec<NonFungibleGlobalId> {
+            let mut nft_record: Vec<NonFungibleGlobalId> = Vec::new();
+
+            for bucket in b_nft_assets {
+                nft_record.extend(nft_global_ids(bucket));
+            }
+
+            self.limits.check_side("B", &nft_record, &HashMap::new());
+
+            assert_nft_deposit_matches(&self.expected_nfts, &self.b_nft_wants, &nft_record);
+
+            nft_record
+        }
+
+        // Buckets of the same resource are combined first so that splitting a payment across several buckets
+        // counts towards one total. Every asked resource and any protocol fee must be covered, nothing else
+        // may be sent, and anything above that comes back as change.
+
+        fn take_token_payment(
+            &self,
+            b_token_assets: Vec<Bucket>,
+            ask: &HashMap<ResourceAddress, Decimal>,
+            fee_amounts: &HashMap<ResourceAddress, Decimal>,
+        ) -> (Vec<Bucket>, Vec<Bucket>, Vec<Bucket>) {
+            let mut deposited_amounts: HashMap<ResourceAddress, Decimal> = HashMap::new();
+            for bucket in b_token_assets.iter() {
+                *deposited_amounts
+                    .entry(bucket.resource_address())
+                    .or_insert(Decimal::zero()) += bucket.amount();
+            }
+            self.limits.check_side("B", &[], &deposited_amounts);
+
+            let (taken, change) = take_required_tokens(b_token_assets, &with_fees(ask, fee_amounts));
+
+            // each resource's required amount is split back into the ask and the fee on it
+
+            let mut payment: Vec<Bucket> = vec![];
+            let mut fees: Vec<Bucket> = vec![];
+
+            for (resource_address, mut bucket) in taken.into_iter() {
+                match (ask.contains_key(&resource_address), fee_amounts.get(&resource_address)) {
+                    (true, Some(fee)) => {
+                        fees.push(bucket.take(*fee));
+                        payment.push(bucket);
+                    }
+                    (true, None) => payment.push(bucket),
+                    (false, _) => fees.push(bucket),
+                }
+            }
+
+            (payment, fees, change)
+        }
+
+        // the ask plus any protocol fee, which the taker pays on top
+
+        fn required_tokens(&self) -> HashMap<ResourceAddress, Decimal> {
+            with_fees(&self.expected_tokens, &self.fee_amounts)
+        }
+
+        // fees are held with B's deposit until the trade settles, so a withdrawn deposit gets its fee back
+
+        fn store_fees(&mut self, fees: Vec<Bucket>) {
+            for bucket in fees.into_iter() {
+                self.fee_vaults.put(bucket)
+            }
+        }
+
+        fn pay_fees(&mut self) {
+            for fee in self.fee_vaults.take_all().into_iter() {
+                borrow_component!(self.fee_collector)
+                    .call::<()>("deposit_fee", scrypto_args!(fee));
+            }
+        }
+
+        fn store_b_assets(&mut self, b_assets: Vec<Bucket>) {
+            for bucket in b_assets.into_iter() {
+                self.b_nft_vaults.put(bucket)
+            }
+        }
+
+        // creator royalties are taken out of the fungibles at settlement, before either side collects them
+
+        fn pay_royalties(&mut self) {
+            for share in self.royalties.iter() {
+                let vaults = match share.paid_from {
+                    TradeSide::A => &mut self.a_nft_vaults,
+                    TradeSide::B => &mut self.b_nft_vaults,
+                };
+
+                let royalty = vaults.take(share.resource, share.amount);
+                borrow_component!(share.recipient).call::<()>("deposit", scrypto_args!(royalty));
+            }
+        }
+
+        // Settlement happens on B's first claim - fees and royalties are paid before any of A's assets leave.
+        // Once settled the expiry no longer applies and B can collect whatever is left.
+
+        fn b_claim(&mut self, b_key: Proof, selection: Option<ClaimSelection>) -> Vec<Bucket> {
+            self.require_status(
+                &[TradeStatus::Filled, TradeStatus::Settled],
+                "claim A's assets",
+            );
+            self.check_counterparty(b_key);
+
+            if self.status == TradeStatus::Filled {
+                self.assert_not_expired();
+                self.set_status(TradeStatus::Settled);
+                self.pay_fees();
+                self.pay_royalties();
+            }
+
+            assert!(
+                !self.a_nft_vaults.is_empty(),
+                "A's assets have already been claimed"
+            );
+
+            let buckets = match selection {
+                Some(selection) => self.a_nft_vaults.take_selected(&selection),
+                None => self.a_nft_vaults.take_all(),
+            };
+            self.emit_claimed_by_b(&buckets);
+
+            buckets
+        }
+
+        fn a_claim(&mut self, selection: Option<ClaimSelection>) -> Vec<Bucket> {
+            self.require_status(&[TradeStatus::Settled], "claim new assets");
+            assert!(
+                !self.b_nft_vaults.is_empty(),
+                "New assets have already been claimed"
+            );
+
+            let buckets = match selection {
+                Some(selection) => self.b_nft_vaults.take_selected(&selection),
+                None => self.b_nft_vaults.take_all(),
+            };
+            let (nfts, tokens) = asset_lists(&buckets);
+
+            Runtime::emit_event(ClaimedByAEvent {
+                component: Runtime::global_address(),
+                a_key_id: self.a_vault_key_id.clone(),
+                nfts,
+                tokens,
             });
-            self.badge_sweeper.authorize(|| {
-                borrow_resource_manager!(self.a_vault_key).update_non_fungible_data(
-                    &self.a_vault_key_id,
-                    "ready_to_claim",
-                    true,
-                )
+
+            buckets
+        }
+
+        // Shared by cancel and cancel_partial - None empties every vault, a selection takes one chunk of them.
+
+        fn a_withdraw(&mut self, selection: Option<ClaimSelection>) -> Vec<Bucket> {
+            self.require_status(
+                &[
+                    TradeStatus::Open,
+                    TradeStatus::Filled,
+                    TradeStatus::Cancelled,
+                    TradeStatus::Expired,
+                ],
+                "cancel",
+            );
+
+            if matches!(self.status, TradeStatus::Open | TradeStatus::Filled) {
+                if self.is_expired() {
+                    self.set_status(TradeStatus::Expired);
+                } else {
+                    self.set_status(TradeStatus::Cancelled);
+                }
+
+                // B's deposit goes back to them rather than waiting in a trade that can no longer pay for it
+
+                if let Some(refund_account) = self.b_refund_account {
+                    let refund = self.release_b_deposit(false);
+                    borrow_component!(refund_account)
+                        .call::<()>("deposit_batch", scrypto_args!(refund));
+                }
+            }
+
+            assert!(
+                !self.a_nft_vaults.is_empty(),
+                "A's assets have already been withdrawn"
+            );
+
+            let buckets = match selection {
+                Some(selection) => self.a_nft_vaults.take_selected(&selection),
+                None => self.a_nft_vaults.take_all(),
+            };
+            let (nfts, tokens) = asset_lists(&buckets);
+
+            if self.status == TradeStatus::Expired {
+                Runtime::emit_event(ExpiredEvent {
+                    component: Runtime::global_address(),
+                    a_key_id: self.a_vault_key_id.clone(),
+                    nfts,
+                    tokens,
+                });
+            } else {
+                Runtime::emit_event(CancelledEvent {
+                    component: Runtime::global_address(),
+                    a_key_id: self.a_vault_key_id.clone(),
+                    nfts,
+                    tokens,
+                });
+            }
+
+            buckets
+        }
+
+        // Empties B's side and unbinds the counterparty. A withdrawn counteroffer takes its terms with it - a
+        // trade that reopens goes back to A's own ask, under a new revision so nobody fills it on terms they
+        // weren't shown.
+
+        fn release_b_deposit(&mut self, reopening: bool) -> Vec<Bucket> {
+            self.b_key_id = None;
+            self.b_refund_account = None;
+            self.b_deposited_nfts = Vec::new();
+            self.b_deposited_tokens = HashMap::new();
+            self.update_receipts("b_nfts_delivered", Vec::<NonFungibleGlobalId>::new());
+
+            if let Some(ask) = self.superseded_ask.take() {
+                self.set_counter_status(ask.counter_id, CounterStatus::Withdrawn);
+
+                if reopening {
+                    self.expected_nfts = ask.nfts;
+                    self.b_nft_wants = ask.wants;
+                    self.expected_tokens = ask.tokens;
+                    self.record_amendment();
+                }
+            }
+
+            let mut buckets = self.b_nft_vaults.take_all();
+            buckets.extend(self.fee_vaults.take_all());
+
+            let (nfts, tokens) = asset_lists(&buckets);
+
+            Runtime::emit_event(CounterpartyWithdrawEvent {
+                component: Runtime::global_address(),
+                a_key_id: self.a_vault_key_id.clone(),
+                nfts,
+                tokens,
             });
 
-            return buckets;
+            buckets
         }
 
-        pub fn cancel(&mut self) -> Vec<Bucket> {
+        fn pending_counter(&self, counter_id: u64) -> CounterOffer {
+            let counter = self
+                .counter_offers
+                .get(&counter_id)
+                .expect("No counteroffer with this id")
+                .clone();
+
             assert!(
-                self.b_nft_vaults.is_empty(),
-                "Trade has already occured, only able to withdraw new assets"
+                counter.status == CounterStatus::Pending,
+                "Counteroffer is {:?}, not pending",
+                counter.status
             );
 
-            let a_assets: Vec<ResourceAddress> = self.a_nft_vaults.keys().cloned().collect();
+            counter
+        }
 
-            let mut buckets: Vec<Bucket> = vec![];
+        // a counteroffer leaving Pending frees its place under MAX_PENDING_COUNTERS
 
-            for resource_address in a_assets.into_iter() {
-                buckets.push(
-                    self.a_nft_vaults
-                        .get_mut(&resource_address)
-                        .unwrap()
-                        .take_all(),
-                )
+        fn set_counter_status(&mut self, counter_id: u64, status: CounterStatus) {
+            let mut counter = self.counter_offers.get_mut(&counter_id).unwrap();
+            if counter.status == CounterStatus::Pending {
+                self.pending_counters -= 1;
             }
+            counter.status = status;
+        }
+
+        fn emit_claimed_by_b(&self, buckets: &[Bucket]) {
+            let (nfts, tokens) = asset_lists(buckets);
+
+            Runtime::emit_event(ClaimedByBEvent {
+                component: Runtime::global_address(),
+                a_key_id: self.a_vault_key_id.clone(),
+                nfts,
+                tokens,
+            });
+        }
 
-            return buckets;
+        // provide optionality for trades to include either tokens, nfts or both tokens and nfts.
+
+        fn b_side_complete(&self) -> bool {
+            (!self.asks_for_nfts() || !self.b_deposited_nfts.is_empty())
+                && (self.required_tokens().is_empty() || !self.b_deposited_tokens.is_empty())
         }
 
-        pub fn claim_my_new_assets(&mut self) -> Vec<Bucket> {
+        // Every method that commits B to A's terms takes the revision B was shown, so an amendment made
+        // after B looked - or a front-end showing stale state - fails the call instead of filling on terms B
+        // never saw.
+
+        fn assert_revision(&self, revision: u64) {
             assert!(
-                !self.b_nft_vaults.is_empty(),
-                "Trade hasn't occured, waiting for trading partner"
+                revision == self.revision,
+                "Trade terms have changed - expected revision {}, the trade is at revision {}",
+                revision,
+                self.revision
             );
+        }
 
-            let b_assets: Vec<ResourceAddress> = self.b_nft_vaults.keys().cloned().collect();
+        fn assert_amendable(&self) {
+            self.require_status(&[TradeStatus::Open], "amend the proposal");
+            assert!(
+                self.b_key_id.is_none(),
+                "A counterparty has deposited into this trade, it can't be amended"
+            );
+            self.assert_not_partially_filled();
+        }
+
+        // Works out the fees, limits and royalties for the amended terms, then publishes them - on both
+        // Escroceipts, as an event and to the hub the trade is listed on.
+
+        fn record_amendment(&mut self) {
+            assert!(
+                !(self.a_nfts.is_empty() && self.a_tokens.is_empty()),
+                "Proposal offers nothing - A's side can't be empty"
+            );
 
-            let mut buckets: Vec<Bucket> = vec![];
+            self.fee_amounts = self.fee_terms.amounts_for(&self.expected_tokens);
+
+            self.limits.check_side("A", &self.a_nfts, &self.a_tokens);
+            self.limits.check_wanted_side(
+                "B",
+                &self.expected_nfts,
+                &self.b_nft_wants,
+                &self.required_tokens(),
+            );
 
-            for resource_address in b_assets.into_iter() {
-                buckets.push(
-                    self.b_nft_vaults
-                        .get_mut(&resource_address)
-                        .unwrap()
-                        .take_all(),
+            let mut royalties = royalty_shares(&self.a_nfts, &self.expected_tokens, TradeSide::B);
+            royalties.extend(collection_royalty_shares(
+                &nfts_per_collection(&self.expected_nfts, &self.b_nft_wants),
+                &self.a_tokens,
+                TradeSide::A,
+            ));
+            self.royalties = royalties;
+
+            self.revision += 1;
+
+            self.update_receipts("a_nft_contents", self.a_nfts.clone());
+            self.update_receipts("a_token_contents", self.a_tokens.clone());
+            self.update_receipts("b_nft_contents", self.expected_nfts.clone());
+            self.update_receipts("b_nft_wants", self.b_nft_wants.clone());
+            self.update_receipts("b_token_contents", self.expected_tokens.clone());
+            self.update_receipts("fee_amounts", self.fee_amounts.clone());
+            self.update_receipts("royalties", self.royalties.clone());
+            self.update_receipts("revision", self.revision);
+
+            Runtime::emit_event(ProposalAmendedEvent {
+                component: Runtime::global_address(),
+                a_key_id: self.a_vault_key_id.clone(),
+                revision: self.revision,
+                a_nfts: self.a_nfts.clone(),
+                a_tokens: self.a_tokens.clone(),
+                b_nfts: self.expected_nfts.clone(),
+                b_wants: self.b_nft_wants.clone(),
+                b_tokens: self.expected_tokens.clone(),
+            });
+
+            let (offered, wanted) = listed_resources(
+                &self.a_nfts,
+                &self.a_tokens,
+                &self.expected_nfts,
+                &self.b_nft_wants,
+                &self.expected_tokens,
+            );
+
+            self.registrar_badge.authorize(|| {
+                borrow_component!(self.hub).call::<()>(
+                    "record_terms",
+                    scrypto_args!(Runtime::global_address(), offered, wanted),
                 )
-            }
-            return buckets;
+            });
         }
 
-        // after accepted
+        fn asks_for_nfts(&self) -> bool {
+            !self.expected_nfts.is_empty() || !self.b_nft_wants.is_empty()
+        }
+
+        // TRADE LIFECYCLE
+
+        // Open -> Filled -> Settled when B deposits in separate steps, Open -> Settled through accept,
+        // Filled -> Open when B withdraws their deposit and Open/Filled -> Cancelled or Expired when A pulls
+        // the proposal. Every method checks the status it needs first, so a claim after cancel or a deposit
+        // after settlement fail the same way. Claims and cancels can be taken in chunks, so a repeated one is
+        // rejected once nothing is left in the side's vault index.
+
+        fn require_status(&self, allowed: &[TradeStatus], action: &str) {
+            require_status(self.status, allowed, action, "trade");
+        }
+
+        // Once the expiry passes B can no longer deposit or claim, while A can still cancel and B can still
+        // withdraw anything they deposited.
+
+        fn is_expired(&self) -> bool {
+            is_expired(&self.expiry)
+        }
+
+        fn assert_not_expired(&self) {
+            assert!(!self.is_expired(), "This trade has expired");
+        }
+
+        // part of A's side has already gone to partial fills, so nobody can take the whole trade any more
+
+        fn assert_not_partially_filled(&self) {
+            assert!(
+                self.filled.is_zero(),
+                "This trade has been partially filled, only the remainder can be filled with fill_partial"
+            );
+        }
+
+        fn update_receipts<V: ScryptoEncode + Clone>(&self, field: &str, value: V) {
+            self.badge_sweeper.authorize(|| {
+                borrow_resource_manager!(self.receipt_addr).update_non_fungible_data(
+                    &self.receipt_gaddr,
+                    field,
+                    value.clone(),
+                )
+            });
+            self.badge_sweeper.authorize(|| {
+                borrow_resource_manager!(self.a_vault_key).update_non_fungible_data(
+                    &self.a_vault_key_id,
+                    field,
+                    value,
+                )
+            });
+        }
+
+        // status is mirrored into both copies of the Escroceipt so wallets can show it
+
+        fn set_status(&mut self, status: TradeStatus) {
+            self.status = status;
+
+            self.update_receipts("status", status);
+
+            // the hub's registry is kept up to date
+
+            self.registrar_badge.authorize(|| {
+                borrow_component!(self.hub).call::<()>(
+                    "record_status",
+                    scrypto_args!(Runtime::global_address(), status),
+                )
+            });
+        }
     }
 }
 
 #[derive(NonFungibleData, ScryptoSbor, Debug)]
 struct Escroceipt {
+    #[mutable]
     a_nft_contents: Vec<NonFungibleGlobalId>,
+    #[mutable]
     a_token_contents: HashMap<ResourceAddress, Decimal>,
+    #[mutable]
     b_nft_contents: Vec<NonFungibleGlobalId>,
+    #[mutable]
     b_token_contents: HashMap<ResourceAddress, Decimal>,
     #[mutable]
-    ready_to_claim: bool,
+    b_nft_wants: Vec<CollectionWant>,
+    // the NFTs B actually handed over, which collection wants don't name in advance
+    #[mutable]
+    b_nfts_delivered: Vec<NonFungibleGlobalId>,
+    #[mutable]
+    status: TradeStatus,
+    expiry: Option<Expiry>,
+    permitted_takers: Vec<PermittedTaker>,
+    fee_terms: ProtocolFee,
+    #[mutable]
+    fee_amounts: HashMap<ResourceAddress, Decimal>,
+    #[mutable]
+    royalties: Vec<RoyaltyShare>,
+    #[mutable]
+    filled_percentage: Decimal,
+    // counts A's amendments to the proposal, starting from 0
+    #[mutable]
+    revision: u64,
+}
+
+// Optional settings for a trade, kept out of the constructor's argument list so new ones can be added
+// without every caller changing.
+
+#[derive(ScryptoSbor, Clone, Debug)]
+pub struct TradeOptions {
+    pub expiry: Option<Expiry>,
+    pub permitted_takers: Vec<PermittedTaker>,
+    // None uses TradeLimits::CEILING
+    pub limits: Option<TradeLimits>,
+    // NFTs wanted by collection rather than by id, on top of the exact ids asked for
+    pub collection_wants: Vec<CollectionWant>,
+}
+
+#[derive(ScryptoSbor, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TradeStatus {
+    Open,
+    Filled,
+    Settled,
+    Cancelled,
+    Expired,
+}
+
+// A trade can expire at a point in time, checked against the Clock to the minute, or at an epoch.
+
+#[derive(ScryptoSbor, Clone, Debug, PartialEq, Eq)]
+pub enum Expiry {
+    AtTime(Instant),
+    AtEpoch(u64),
+}
+
+impl Expiry {
+    pub fn has_passed(&self) -> bool {
+        match self {
+            Expiry::AtTime(instant) => {
+                Clock::current_time_is_at_or_after(*instant, TimePrecision::Minute)
+            }
+            Expiry::AtEpoch(epoch) => Runtime::current_epoch() >= *epoch,
+        }
+    }
+}
+
+// Who may take a trade. Accounts are named by the public key that signs for them, badges either by
+// resource or by a single NFT. An empty list leaves the trade open to anyone.
+
+#[derive(ScryptoSbor, Clone, Debug, PartialEq, Eq)]
+pub enum PermittedTaker {
+    Account(PublicKey),
+    Badge(ResourceAddress),
+    NonFungible(NonFungibleGlobalId),
+}
+
+fn taker_access_rule(permitted_takers: &[PermittedTaker]) -> AccessRule {
+    if permitted_takers.is_empty() {
+        return AccessRule::AllowAll;
+    }
+
+    let takers: Vec<ResourceOrNonFungible> = permitted_takers
+        .iter()
+        .map(|taker| match taker {
+            PermittedTaker::Account(public_key) => {
+                ResourceOrNonFungible::NonFungible(NonFungibleGlobalId::from_public_key(public_key))
+            }
+            PermittedTaker::Badge(resource_address) => {
+                ResourceOrNonFungible::Resource(*resource_address)
+            }
+            PermittedTaker::NonFungible(global_id) => {
+                ResourceOrNonFungible::NonFungible(global_id.clone())
+            }
+        })
+        .collect();
+
+    rule!(require_any_of(takers))
+}
+
+#[derive(NonFungibleData, ScryptoSbor, Debug)]
+struct CounterpartyKey {}
+
+// COUNTEROFFERS
+
+// A counteroffer is B's alternative to the terms A asked for, escrowed in full when it is made. Every
+// counteroffer is kept in a KeyValueStore by id for the front-end to show, and at most MAX_PENDING_COUNTERS can
+// be pending at once so a trade can't be buried under them.
+
+const MAX_PENDING_COUNTERS: u32 = 20;
+
+#[derive(ScryptoSbor, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CounterStatus {
+    Pending,
+    Accepted,
+    Rejected,
+    Withdrawn,
+}
+
+#[derive(ScryptoSbor, Clone, Debug)]
+pub struct CounterOffer {
+    pub id: u64,
+    pub key_id: NonFungibleLocalId,
+    pub refund_account: ComponentAddress,
+    pub nfts: Vec<NonFungibleGlobalId>,
+    pub tokens: HashMap<ResourceAddress, Decimal>,
+    pub fee_amounts: HashMap<ResourceAddress, Decimal>,
+    pub status: CounterStatus,
+}
+
+// each amount scaled by a fraction of the trade and rounded to its token's divisibility
+
+fn scale_amounts(
+    amounts: &HashMap<ResourceAddress, Decimal>,
+    fraction: Decimal,
+    mode: RoundingMode,
+) -> HashMap<ResourceAddress, Decimal> {
+    let mut scaled: HashMap<ResourceAddress, Decimal> = HashMap::new();
+
+    for (resource_address, amount) in amounts.iter() {
+        let divisibility = match borrow_resource_manager!(*resource_address).resource_type() {
+            ResourceType::Fungible { divisibility } => divisibility,
+            _ => DIVISIBILITY_NONE,
+        };
+        scaled.insert(*resource_address, (*amount * fraction).round(divisibility, mode));
+    }
+
+    scaled.retain(|_, amount| *amount > Decimal::zero());
+    scaled
+}
+
+// A's own ask, kept while the terms of an accepted counteroffer stand in for it
+
+#[derive(ScryptoSbor, Clone, Debug)]
+pub struct SupersededAsk {
+    pub counter_id: u64,
+    pub nfts: Vec<NonFungibleGlobalId>,
+    pub wants: Vec<CollectionWant>,
+    pub tokens: HashMap<ResourceAddress, Decimal>,
+}
+
+// The distinct resources on each side, as listed on the hub - NFT collections and tokens offered, and NFT
+// collections, wanted collections and tokens asked for.
+
+fn listed_resources(
+    a_nfts: &[NonFungibleGlobalId],
+    a_tokens: &HashMap<ResourceAddress, Decimal>,
+    b_nfts: &[NonFungibleGlobalId],
+    b_wants: &[CollectionWant],
+    b_tokens: &HashMap<ResourceAddress, Decimal>,
+) -> (Vec<ResourceAddress>, Vec<ResourceAddress>) {
+    let mut offered: Vec<ResourceAddress> = Vec::new();
+    for resource_address in a_nfts
+        .iter()
+        .map(|nft| nft.resource_address())
+        .chain(a_tokens.keys().cloned())
+    {
+        if !offered.contains(&resource_address) {
+            offered.push(resource_address);
+        }
+    }
+
+    let mut wanted: Vec<ResourceAddress> = Vec::new();
+    for resource_address in b_nfts
+        .iter()
+        .map(|nft| nft.resource_address())
+        .chain(b_wants.iter().map(|want| want.resource))
+        .chain(b_tokens.keys().cloned())
+    {
+        if !wanted.contains(&resource_address) {
+            wanted.push(resource_address);
+        }
+    }
+
+    (offered, wanted)
+}
+
+// an ask plus the protocol fee the taker pays on top of it
+
+fn with_fees(
+    ask: &HashMap<ResourceAddress, Decimal>,
+    fee_amounts: &HashMap<ResourceAddress, Decimal>,
+) -> HashMap<ResourceAddress, Decimal> {
+    let mut required = ask.clone();
+
+    for (resource_address, fee) in fee_amounts.iter() {
+        *required.entry(*resource_address).or_insert(Decimal::zero()) += *fee;
+    }
+
+    required
+}
+
+// Enumerates every local id in a non-fungible bucket. Only ids are read, so this works for buckets holding
+// any number of NFTs and for any NonFungibleData schema.
+
+pub(crate) fn nft_global_ids(bucket: &Bucket) -> Vec<NonFungibleGlobalId> {
+    let resource_address = bucket.resource_address();
+
+    bucket
+        .non_fungible_local_ids()
+        .into_iter()
+        .map(|local_id| NonFungibleGlobalId::new(resource_address, local_id))
+        .collect()
+}
+
+// the NFTs and token amounts held in a set of buckets, used to describe assets in events
+
+pub(crate) fn asset_lists(
+    buckets: &[Bucket],
+) -> (Vec<NonFungibleGlobalId>, HashMap<ResourceAddress, Decimal>) {
+    let mut nfts: Vec<NonFungibleGlobalId> = Vec::new();
+    let mut tokens: HashMap<ResourceAddress, Decimal> = HashMap::new();
+
+    for bucket in buckets.iter() {
+        if is_non_fungible(bucket) {
+            nfts.extend(nft_global_ids(bucket));
+        } else if !bucket.is_empty() {
+            *tokens
+                .entry(bucket.resource_address())
+                .or_insert(Decimal::zero()) += bucket.amount();
+        }
+    }
+
+    (nfts, tokens)
+}
+
+pub(crate) fn is_non_fungible(bucket: &Bucket) -> bool {
+    is_non_fungible_resource(bucket.resource_address())
+}
+
+// Shared by Barter and MultiSwap. Buckets of the same resource are combined, the required amount of each is
+// taken and the rest returned as change. A deposit missing a required resource, short of one, or including one
+// that isn't required is rejected.
+
+pub(crate) fn take_required_tokens(
+    token_assets: Vec<Bucket>,
+    required: &HashMap<ResourceAddress, Decimal>,
+) -> (HashMap<ResourceAddress, Bucket>, Vec<Bucket>) {
+    let mut combined: HashMap<ResourceAddress, Bucket> = HashMap::new();
+
+    for bucket in token_assets.into_iter() {
+        assert!(
+            required.contains_key(&bucket.resource_address()),
+            "Token deposit includes {:?}, which isn't required",
+            bucket.resource_address()
+        );
+        match combined.get_mut(&bucket.resource_address()) {
+            Some(existing) => existing.put(bucket),
+            None => {
+                combined.insert(bucket.resource_address(), bucket);
+            }
+        }
+    }
+
+    let mut taken: HashMap<ResourceAddress, Bucket> = HashMap::new();
+    let mut change: Vec<Bucket> = vec![];
+
+    for (resource_address, amount) in required.iter() {
+        let mut deposited = combined
+            .remove(resource_address)
+            .unwrap_or_else(|| panic!("Token deposit is missing {:?}", resource_address));
+
+        assert!(
+            deposited.amount() >= *amount,
+            "Token deposit of {} {:?} is below the {} required",
+            deposited.amount(),
+            resource_address,
+            amount
+        );
+
+        taken.insert(*resource_address, deposited.take(*amount));
+        change.push(deposited);
+    }
+
+    (taken, change)
+}
+
+// status and expiry checks shared by Barter and MultiSwap - subject names the component in the error
+
+pub(crate) fn require_status(
+    status: TradeStatus,
+    allowed: &[TradeStatus],
+    action: &str,
+    subject: &str,
+) {
+    assert!(
+        allowed.contains(&status),
+        "Unable to {} while the {} is {:?}",
+        action,
+        subject,
+        status
+    );
+}
+
+pub(crate) fn is_expired(expiry: &Option<Expiry>) -> bool {
+    match expiry {
+        Some(expiry) => expiry.has_passed(),
+        None => false,
+    }
 }
@@ -0,0 +1,54 @@
+use scrypto::prelude::*;
+
+#[blueprint]
+mod bootstrapbench {
+    /// This is a bootstrap struct which creates large numbers of distinct resources for sizing trades.
+    struct Bootstrapbench {}
+
+    impl Bootstrapbench {
+        /// Creates `token_count` fungible resources and `collection_count` NFT collections of `nfts_per_collection`
+        /// NFTs each. Offering the lot in a Barter and calling cancel or accept shows how the cost of the
+        /// withdraw loops grows with the number of distinct resources in a trade. With `royalty` set every
+        /// collection takes that percentage, paid to that account, so settlement pays a royalty per collection.
+        pub fn bootstrap_bench(
+            token_count: u32,
+            collection_count: u32,
+            nfts_per_collection: u32,
+            royalty: Option<(Decimal, ComponentAddress)>,
+        ) -> Vec<Bucket> {
+            let mut buckets: Vec<Bucket> = Vec::new();
+
+            // fungible resources, one bucket each
+            for i in 0..token_count {
+                let token: Bucket = ResourceBuilder::new_fungible()
+                    .metadata("name", format!("Bench token {}", i))
+                    .metadata("symbol", format!("BT{}", i))
+                    .mint_initial_supply(1000);
+                buckets.push(token);
+            }
+
+            // non fungible collections, one bucket per collection
+            for i in 0..collection_count {
+                let mut builder = ResourceBuilder::new_uuid_non_fungible()
+                    .metadata("name", format!("Bench collection {}", i))
+                    .metadata("symbol", format!("BC{}", i));
+                if let Some((percentage, account)) = royalty {
+                    builder = builder
+                        .metadata("royalty_percentage", percentage.to_string())
+                        .metadata("royalty_account", account.to_hex());
+                }
+                let collection: Bucket = builder.mint_initial_supply((0..nfts_per_collection).map(|n| BenchItem {
+                        name: format!("#{:04}", n),
+                    }));
+                buckets.push(collection);
+            }
+
+            return buckets;
+        }
+    }
+}
+
+#[derive(NonFungibleData, ScryptoSbor)]
+struct BenchItem {
+    name: String,
+}
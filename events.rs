@@ -0,0 +1,153 @@
+use crate::matching::CollectionWant;
+use scrypto::prelude::*;
+
+// TRADE EVENTS
+
+// Emitted by Barter on every lifecycle transition so indexers and notification services can follow trades
+// without polling component state. Every event carries the trade's component address, the local id of A's
+// key and the assets involved in that step.
+
+#[derive(ScryptoSbor, ScryptoEvent, Clone, Debug)]
+pub struct ProposalCreatedEvent {
+    pub component: ComponentAddress,
+    pub a_key_id: NonFungibleLocalId,
+    pub a_nfts: Vec<NonFungibleGlobalId>,
+    pub a_tokens: HashMap<ResourceAddress, Decimal>,
+    pub b_nfts: Vec<NonFungibleGlobalId>,
+    pub b_wants: Vec<CollectionWant>,
+    pub b_tokens: HashMap<ResourceAddress, Decimal>,
+}
+
+// B put assets into the trade, through either deposit method or accept
+
+#[derive(ScryptoSbor, ScryptoEvent, Clone, Debug)]
+pub struct CounterpartyDepositEvent {
+    pub component: ComponentAddress,
+    pub a_key_id: NonFungibleLocalId,
+    pub nfts: Vec<NonFungibleGlobalId>,
+    pub tokens: HashMap<ResourceAddress, Decimal>,
+}
+
+#[derive(ScryptoSbor, ScryptoEvent, Clone, Debug)]
+pub struct CounterpartyWithdrawEvent {
+    pub component: ComponentAddress,
+    pub a_key_id: NonFungibleLocalId,
+    pub nfts: Vec<NonFungibleGlobalId>,
+    pub tokens: HashMap<ResourceAddress, Decimal>,
+}
+
+#[derive(ScryptoSbor, ScryptoEvent, Clone, Debug)]
+pub struct ClaimedByBEvent {
+    pub component: ComponentAddress,
+    pub a_key_id: NonFungibleLocalId,
+    pub nfts: Vec<NonFungibleGlobalId>,
+    pub tokens: HashMap<ResourceAddress, Decimal>,
+}
+
+#[derive(ScryptoSbor, ScryptoEvent, Clone, Debug)]
+pub struct ClaimedByAEvent {
+    pub component: ComponentAddress,
+    pub a_key_id: NonFungibleLocalId,
+    pub nfts: Vec<NonFungibleGlobalId>,
+    pub tokens: HashMap<ResourceAddress, Decimal>,
+}
+
+// A changed an open proposal - carries the terms as they stand after the amendment
+
+#[derive(ScryptoSbor, ScryptoEvent, Clone, Debug)]
+pub struct ProposalAmendedEvent {
+    pub component: ComponentAddress,
+    pub a_key_id: NonFungibleLocalId,
+    pub revision: u64,
+    pub a_nfts: Vec<NonFungibleGlobalId>,
+    pub a_tokens: HashMap<ResourceAddress, Decimal>,
+    pub b_nfts: Vec<NonFungibleGlobalId>,
+    pub b_wants: Vec<CollectionWant>,
+    pub b_tokens: HashMap<ResourceAddress, Decimal>,
+}
+
+#[derive(ScryptoSbor, ScryptoEvent, Clone, Debug)]
+pub struct CancelledEvent {
+    pub component: ComponentAddress,
+    pub a_key_id: NonFungibleLocalId,
+    pub nfts: Vec<NonFungibleGlobalId>,
+    pub tokens: HashMap<ResourceAddress, Decimal>,
+}
+
+// A cancelled after the trade's expiry had passed
+
+#[derive(ScryptoSbor, ScryptoEvent, Clone, Debug)]
+pub struct ExpiredEvent {
+    pub component: ComponentAddress,
+    pub a_key_id: NonFungibleLocalId,
+    pub nfts: Vec<NonFungibleGlobalId>,
+    pub tokens: HashMap<ResourceAddress, Decimal>,
+}
+
+// a taker filled part of a fungible-for-fungible trade - filled is the fraction of the trade filled so far
+
+#[derive(ScryptoSbor, ScryptoEvent, Clone, Debug)]
+pub struct PartialFillEvent {
+    pub component: ComponentAddress,
+    pub a_key_id: NonFungibleLocalId,
+    pub fraction: Decimal,
+    pub filled: Decimal,
+    pub paid: HashMap<ResourceAddress, Decimal>,
+    pub received: HashMap<ResourceAddress, Decimal>,
+}
+
+// B escrowed a counteroffer - the tokens include the protocol fee on the offer
+
+#[derive(ScryptoSbor, ScryptoEvent, Clone, Debug)]
+pub struct CounterOfferMadeEvent {
+    pub component: ComponentAddress,
+    pub a_key_id: NonFungibleLocalId,
+    pub counter_id: u64,
+    pub nfts: Vec<NonFungibleGlobalId>,
+    pub tokens: HashMap<ResourceAddress, Decimal>,
+}
+
+// A rejected a counteroffer and it was refunded to refund_account
+
+#[derive(ScryptoSbor, ScryptoEvent, Clone, Debug)]
+pub struct CounterOfferRejectedEvent {
+    pub component: ComponentAddress,
+    pub a_key_id: NonFungibleLocalId,
+    pub counter_id: u64,
+    pub refund_account: ComponentAddress,
+}
+
+#[derive(ScryptoSbor, ScryptoEvent, Clone, Debug)]
+pub struct CounterOfferWithdrawnEvent {
+    pub component: ComponentAddress,
+    pub a_key_id: NonFungibleLocalId,
+    pub counter_id: u64,
+}
+
+// MULTI-PARTY SWAPS
+
+// Emitted by MultiSwap. participant is the index of the participant's leg in the swap.
+
+#[derive(ScryptoSbor, ScryptoEvent, Clone, Debug)]
+pub struct SwapLegDepositedEvent {
+    pub component: ComponentAddress,
+    pub participant: u32,
+    pub nfts: Vec<NonFungibleGlobalId>,
+    pub tokens: HashMap<ResourceAddress, Decimal>,
+}
+
+#[derive(ScryptoSbor, ScryptoEvent, Clone, Debug)]
+pub struct SwapLegWithdrawnEvent {
+    pub component: ComponentAddress,
+    pub participant: u32,
+    pub nfts: Vec<NonFungibleGlobalId>,
+    pub tokens: HashMap<ResourceAddress, Decimal>,
+}
+
+#[derive(ScryptoSbor, ScryptoEvent, Clone, Debug)]
+pub struct SwapLegClaimedEvent {
+    pub component: ComponentAddress,
+    pub participant: u32,
+    pub nfts: Vec<NonFungibleGlobalId>,
+    pub tokens: HashMap<ResourceAddress, Decimal>,
+}
@@ -0,0 +1,136 @@
+use scrypto::prelude::*;
+
+// OVERVIEW OF BLUEPRINT
+
+// Collects the protocol fee charged on TROVE trades. Barter components read the fee terms from here when a
+// proposal is created, so both parties can see the cost on the Escroceipt before they commit, and pay the
+// fee in at settlement. The admin badge returned on instantiation can change the terms for new trades and
+// withdraw whatever has been collected.
+
+#[blueprint]
+mod fee_collector {
+
+    struct FeeCollector {
+        fee_terms: ProtocolFee,
+        fee_vaults: HashMap<ResourceAddress, Vault>,
+    }
+
+    impl FeeCollector {
+        pub fn instantiate_fee_collector(fee_terms: ProtocolFee) -> (ComponentAddress, Bucket) {
+            fee_terms.validate();
+
+            let admin_badge: Bucket = ResourceBuilder::new_fungible()
+                .divisibility(DIVISIBILITY_NONE)
+                .metadata("name", "TROVE fee admin")
+                .mint_initial_supply(1);
+
+            let rules = AccessRulesConfig::new()
+                .method(
+                    "set_fee_terms",
+                    rule!(require(admin_badge.resource_address())),
+                    LOCKED,
+                )
+                .method(
+                    "withdraw_fees",
+                    rule!(require(admin_badge.resource_address())),
+                    LOCKED,
+                )
+                .default(AccessRule::AllowAll, AccessRule::DenyAll);
+
+            let component = Self {
+                fee_terms,
+                fee_vaults: HashMap::new(),
+            }
+            .instantiate();
+            let component_address = component.globalize_with_access_rules(rules);
+
+            (component_address, admin_badge)
+        }
+
+        pub fn fee_terms(&self) -> ProtocolFee {
+            self.fee_terms.clone()
+        }
+
+        // only affects trades created afterwards - existing trades keep the terms on their Escroceipt
+
+        pub fn set_fee_terms(&mut self, fee_terms: ProtocolFee) {
+            fee_terms.validate();
+            self.fee_terms = fee_terms;
+        }
+
+        pub fn deposit_fee(&mut self, fee: Bucket) {
+            self.fee_vaults
+                .entry(fee.resource_address())
+                .or_insert(Vault::new(fee.resource_address()))
+                .put(fee)
+        }
+
+        pub fn withdraw_fees(&mut self, resource_address: ResourceAddress) -> Bucket {
+            self.fee_vaults
+                .get_mut(&resource_address)
+                .expect("No fees have been collected in this resource")
+                .take_all()
+        }
+    }
+}
+
+// A flat amount of XRD per trade, or a percentage of each token on the fungible side of the trade.
+// Either way the fee is paid by the taker on top of the ask, so A receives exactly what they asked for.
+
+#[derive(ScryptoSbor, Clone, Debug, PartialEq, Eq)]
+pub enum ProtocolFee {
+    Flat(Decimal),
+    Percentage(Decimal),
+}
+
+impl ProtocolFee {
+    pub fn validate(&self) {
+        match self {
+            ProtocolFee::Flat(amount) => {
+                assert!(*amount >= Decimal::zero(), "Flat fee can't be negative")
+            }
+            ProtocolFee::Percentage(percentage) => assert!(
+                *percentage >= Decimal::zero() && *percentage <= dec!("100"),
+                "Fee percentage must be between 0 and 100"
+            ),
+        }
+    }
+
+    // the amount owed per resource for a trade asking for the given tokens
+
+    pub fn amounts_for(
+        &self,
+        asked_tokens: &HashMap<ResourceAddress, Decimal>,
+    ) -> HashMap<ResourceAddress, Decimal> {
+        let mut amounts: HashMap<ResourceAddress, Decimal> = HashMap::new();
+
+        match self {
+            ProtocolFee::Flat(amount) => {
+                amounts.insert(RADIX_TOKEN, *amount);
+            }
+            ProtocolFee::Percentage(percentage) => {
+                for (resource_address, ask) in asked_tokens.iter() {
+                    let fee = *ask * *percentage / dec!("100");
+                    amounts.insert(
+                        *resource_address,
+                        round_up_to_divisibility(*resource_address, fee),
+                    );
+                }
+            }
+        }
+
+        amounts.retain(|_, amount| *amount > Decimal::zero());
+        amounts
+    }
+}
+
+// a percentage can land between the smallest units of a token, so the fee is rounded up to one it can be paid in
+
+fn round_up_to_divisibility(resource_address: ResourceAddress, amount: Decimal) -> Decimal {
+    match borrow_resource_manager!(resource_address).resource_type() {
+        ResourceType::Fungible { divisibility } => {
+            amount.round(divisibility, RoundingMode::TowardsPositiveInfinity)
+        }
+        _ => amount,
+    }
+}
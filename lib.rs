@@ -1,3 +1,14 @@
 mod bootstrap;
 mod bootstrap_partner;
+mod bootstrap_bench;
 mod barter_olc;
+mod events;
+mod fee_collector;
+mod matching;
+mod multi_swap;
+mod predicates;
+mod royalties;
+mod trove_hub;
+mod validation;
+mod vault_bench;
+mod vault_store;
@@ -0,0 +1,390 @@
+use crate::predicates::{nft_fields, FieldPredicate};
+use scrypto::prelude::*;
+
+// NFT SET MATCHING
+
+// Compares the NFTs a counterparty hands over against the NonFungibleGlobalIds listed in a proposal.
+// Sorting both lists and comparing them is not enough on its own - the comparison has to be between
+// the lists themselves, and it has to be able to say which ids were the problem so the front-end
+// can show the user what went wrong.
+
+// Every expected id must be delivered exactly once and nothing else may be delivered.
+
+// A proposal can also want NFTs by collection rather than by id - "any 2 from this collection", optionally only
+// from a set or range of local ids. Exact ids are matched first and whatever else was delivered is assigned to
+// the collection wants, so one NFT never counts towards two wants. A want can also require its NFTs' data to
+// match field predicates - see predicates.rs.
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct NftSetMismatch {
+    pub missing: Vec<NonFungibleGlobalId>,
+    pub extra: Vec<NonFungibleGlobalId>,
+    pub duplicates: Vec<NonFungibleGlobalId>,
+    pub unmet_wants: Vec<CollectionWant>,
+}
+
+impl NftSetMismatch {
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty()
+            && self.extra.is_empty()
+            && self.duplicates.is_empty()
+            && self.unmet_wants.is_empty()
+    }
+}
+
+#[derive(ScryptoSbor, Clone, Debug, PartialEq, Eq)]
+pub struct CollectionWant {
+    pub resource: ResourceAddress,
+    pub count: u32,
+    // None accepts any id from the collection
+    pub allowed: Option<AllowedIds>,
+    // every predicate must match the NFT's data
+    pub predicates: Vec<FieldPredicate>,
+}
+
+#[derive(ScryptoSbor, Clone, Debug, PartialEq, Eq)]
+pub enum AllowedIds {
+    Set(Vec<NonFungibleLocalId>),
+    // inclusive, for collections with integer local ids
+    IntegerRange(u64, u64),
+}
+
+impl CollectionWant {
+    pub fn admits(&self, nft: &NonFungibleGlobalId) -> bool {
+        if nft.resource_address() != self.resource {
+            return false;
+        }
+
+        let allowed = match &self.allowed {
+            None => true,
+            Some(AllowedIds::Set(ids)) => ids.contains(nft.local_id()),
+            Some(AllowedIds::IntegerRange(from, to)) => match nft.local_id() {
+                NonFungibleLocalId::Integer(id) => id.value() >= *from && id.value() <= *to,
+                _ => false,
+            },
+        };
+
+        if !allowed || self.predicates.is_empty() {
+            return allowed;
+        }
+
+        let fields = nft_fields(nft);
+        self.predicates
+            .iter()
+            .all(|predicate| predicate.matches(&fields))
+    }
+}
+
+// how many NFTs each collection contributes to a side, counting collection wants at their full count
+
+pub fn nfts_per_collection(
+    nfts: &[NonFungibleGlobalId],
+    wants: &[CollectionWant],
+) -> BTreeMap<ResourceAddress, usize> {
+    let mut per_collection: BTreeMap<ResourceAddress, usize> = BTreeMap::new();
+
+    for nft in nfts.iter() {
+        *per_collection.entry(nft.resource_address()).or_insert(0) += 1;
+    }
+    for want in wants.iter() {
+        *per_collection.entry(want.resource).or_insert(0) += want.count as usize;
+    }
+
+    per_collection
+}
+
+pub fn match_nft_sets(
+    expected: &[NonFungibleGlobalId],
+    delivered: &[NonFungibleGlobalId],
+) -> NftSetMismatch {
+    let mut expected_count: BTreeMap<&NonFungibleGlobalId, usize> = BTreeMap::new();
+    for id in expected.iter() {
+        *expected_count.entry(id).or_insert(0) += 1;
+    }
+
+    let mut delivered_count: BTreeMap<&NonFungibleGlobalId, usize> = BTreeMap::new();
+    for id in delivered.iter() {
+        *delivered_count.entry(id).or_insert(0) += 1;
+    }
+
+    let mut mismatch = NftSetMismatch::default();
+
+    for (id, wanted) in expected_count.iter() {
+        let received = delivered_count.get(id).cloned().unwrap_or(0);
+        if received < *wanted {
+            mismatch.missing.push((*id).clone());
+        }
+    }
+
+    for (id, received) in delivered_count.iter() {
+        let wanted = expected_count.get(id).cloned().unwrap_or(0);
+        if wanted == 0 {
+            mismatch.extra.push((*id).clone());
+        } else if *received > *wanted {
+            mismatch.duplicates.push((*id).clone());
+        }
+    }
+
+    mismatch
+}
+
+// Exact ids first, then the rest of the delivery against the collection wants. Each want is expanded into one
+// slot per NFT it asks for and the leftover NFTs are matched to slots with augmenting paths, so a delivery that
+// can satisfy every want is never rejected because of the order it arrived in.
+
+pub fn match_nft_deposit(
+    expected: &[NonFungibleGlobalId],
+    wants: &[CollectionWant],
+    delivered: &[NonFungibleGlobalId],
+) -> NftSetMismatch {
+    if wants.is_empty() {
+        return match_nft_sets(expected, delivered);
+    }
+
+    let expected_ids: BTreeSet<&NonFungibleGlobalId> = expected.iter().collect();
+    let (exact, rest): (Vec<NonFungibleGlobalId>, Vec<NonFungibleGlobalId>) = delivered
+        .iter()
+        .cloned()
+        .partition(|nft| expected_ids.contains(nft));
+
+    let mut mismatch = match_nft_sets(expected, &exact);
+
+    // an id delivered twice may only fill one slot
+
+    let mut seen: BTreeSet<NonFungibleGlobalId> = BTreeSet::new();
+    let mut leftover: Vec<NonFungibleGlobalId> = Vec::new();
+    for nft in rest.into_iter() {
+        if seen.insert(nft.clone()) {
+            leftover.push(nft);
+        } else if !mismatch.duplicates.contains(&nft) {
+            mismatch.duplicates.push(nft);
+        }
+    }
+
+    let slots: Vec<usize> = wants
+        .iter()
+        .enumerate()
+        .flat_map(|(index, want)| std::iter::repeat(index).take(want.count as usize))
+        .collect();
+
+    // admits is worked out once per NFT and want, it may have to read the NFT's data
+
+    let admitted: Vec<Vec<bool>> = leftover
+        .iter()
+        .map(|nft| wants.iter().map(|want| want.admits(nft)).collect())
+        .collect();
+
+    let mut slot_holder: Vec<Option<usize>> = vec![None; slots.len()];
+
+    for (candidate, nft) in leftover.iter().enumerate() {
+        let mut visited = vec![false; slots.len()];
+        if !assign_slot(candidate, &slots, &admitted, &mut slot_holder, &mut visited) {
+            mismatch.extra.push(nft.clone());
+        }
+    }
+
+    for (slot, want_index) in slots.iter().enumerate() {
+        if slot_holder[slot].is_none() && !mismatch.unmet_wants.contains(&wants[*want_index]) {
+            mismatch.unmet_wants.push(wants[*want_index].clone());
+        }
+    }
+
+    mismatch
+}
+
+fn assign_slot(
+    candidate: usize,
+    slots: &[usize],
+    admitted: &[Vec<bool>],
+    slot_holder: &mut [Option<usize>],
+    visited: &mut [bool],
+) -> bool {
+    for (slot, want_index) in slots.iter().enumerate() {
+        if visited[slot] || !admitted[candidate][*want_index] {
+            continue;
+        }
+        visited[slot] = true;
+
+        let free = match slot_holder[slot] {
+            None => true,
+            Some(holder) => assign_slot(holder, slots, admitted, slot_holder, visited),
+        };
+
+        if free {
+            slot_holder[slot] = Some(candidate);
+            return true;
+        }
+    }
+
+    false
+}
+
+// panics with the offending ids, so a failed deposit reports exactly what was wrong with it
+
+pub fn assert_nft_deposit_matches(
+    expected: &[NonFungibleGlobalId],
+    wants: &[CollectionWant],
+    delivered: &[NonFungibleGlobalId],
+) {
+    let mismatch = match_nft_deposit(expected, wants, delivered);
+
+    assert!(
+        mismatch.is_empty(),
+        "Deposit does not meet A's expectations - missing: {:?}, not requested: {:?}, duplicated: {:?}, unmet wants: {:?}",
+        mismatch.missing,
+        mismatch.extra,
+        mismatch.duplicates,
+        mismatch.unmet_wants
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nft(collection: ResourceAddress, id: u64) -> NonFungibleGlobalId {
+        NonFungibleGlobalId::new(collection, NonFungibleLocalId::integer(id))
+    }
+
+    fn want(collection: ResourceAddress, count: u32, allowed: Option<AllowedIds>) -> CollectionWant {
+        CollectionWant {
+            resource: collection,
+            count,
+            allowed,
+            predicates: Vec::new(),
+        }
+    }
+
+    const COLLECTION: ResourceAddress = ECDSA_SECP256K1_TOKEN;
+    const OTHER_COLLECTION: ResourceAddress = EDDSA_ED25519_TOKEN;
+
+    #[test]
+    fn exact_delivery_matches() {
+        let expected = vec![nft(COLLECTION, 1), nft(OTHER_COLLECTION, 2)];
+        let delivered = vec![nft(OTHER_COLLECTION, 2), nft(COLLECTION, 1)];
+
+        assert!(match_nft_sets(&expected, &delivered).is_empty());
+    }
+
+    #[test]
+    fn missing_id_is_reported() {
+        let expected = vec![nft(COLLECTION, 1), nft(COLLECTION, 2)];
+        let delivered = vec![nft(COLLECTION, 1)];
+
+        let mismatch = match_nft_sets(&expected, &delivered);
+        assert_eq!(mismatch.missing, vec![nft(COLLECTION, 2)]);
+        assert!(mismatch.extra.is_empty());
+    }
+
+    #[test]
+    fn extra_id_is_reported() {
+        let expected = vec![nft(COLLECTION, 1)];
+        let delivered = vec![nft(COLLECTION, 1), nft(COLLECTION, 3)];
+
+        let mismatch = match_nft_sets(&expected, &delivered);
+        assert_eq!(mismatch.extra, vec![nft(COLLECTION, 3)]);
+        assert!(mismatch.missing.is_empty());
+    }
+
+    // a swapped id of the right collection and count is still the wrong NFT
+
+    #[test]
+    fn substituted_id_is_reported() {
+        let expected = vec![nft(COLLECTION, 1)];
+        let delivered = vec![nft(COLLECTION, 2)];
+
+        let mismatch = match_nft_sets(&expected, &delivered);
+        assert_eq!(mismatch.missing, vec![nft(COLLECTION, 1)]);
+        assert_eq!(mismatch.extra, vec![nft(COLLECTION, 2)]);
+    }
+
+    #[test]
+    fn repeated_id_is_reported() {
+        let expected = vec![nft(COLLECTION, 1), nft(COLLECTION, 2)];
+        let delivered = vec![nft(COLLECTION, 1), nft(COLLECTION, 1)];
+
+        let mismatch = match_nft_sets(&expected, &delivered);
+        assert_eq!(mismatch.missing, vec![nft(COLLECTION, 2)]);
+        assert_eq!(mismatch.duplicates, vec![nft(COLLECTION, 1)]);
+    }
+
+    #[test]
+    fn repeated_id_cannot_fill_two_slots_of_a_want() {
+        let wants = vec![want(COLLECTION, 2, None)];
+        let delivered = vec![nft(COLLECTION, 1), nft(COLLECTION, 1)];
+
+        let mismatch = match_nft_deposit(&[], &wants, &delivered);
+        assert_eq!(mismatch.duplicates, vec![nft(COLLECTION, 1)]);
+        assert_eq!(mismatch.unmet_wants, wants);
+    }
+
+    // the same NFT can't count once as an exact id and again towards a want with a count of one
+
+    #[test]
+    fn exact_id_is_not_counted_against_a_want() {
+        let expected = vec![nft(COLLECTION, 1)];
+        let wants = vec![want(COLLECTION, 1, None)];
+        let delivered = vec![nft(COLLECTION, 1)];
+
+        let mismatch = match_nft_deposit(&expected, &wants, &delivered);
+        assert_eq!(mismatch.unmet_wants, wants);
+
+        let delivered = vec![nft(COLLECTION, 1), nft(COLLECTION, 1)];
+
+        let mismatch = match_nft_deposit(&expected, &wants, &delivered);
+        assert_eq!(mismatch.duplicates, vec![nft(COLLECTION, 1)]);
+        assert!(!mismatch.is_empty());
+    }
+
+    #[test]
+    fn want_rejects_ids_outside_its_allowed_set_and_range() {
+        let wants = vec![want(
+            COLLECTION,
+            1,
+            Some(AllowedIds::Set(vec![NonFungibleLocalId::integer(7)])),
+        )];
+
+        let mismatch = match_nft_deposit(&[], &wants, &[nft(COLLECTION, 8)]);
+        assert_eq!(mismatch.extra, vec![nft(COLLECTION, 8)]);
+        assert_eq!(mismatch.unmet_wants, wants);
+
+        let wants = vec![want(COLLECTION, 1, Some(AllowedIds::IntegerRange(10, 20)))];
+
+        assert!(match_nft_deposit(&[], &wants, &[nft(COLLECTION, 20)]).is_empty());
+        assert!(!match_nft_deposit(&[], &wants, &[nft(COLLECTION, 21)]).is_empty());
+        assert!(!match_nft_deposit(&[], &wants, &[nft(OTHER_COLLECTION, 15)]).is_empty());
+    }
+
+    // #1 fits both wants and arrives first, so it takes the broad want until #2 - which only fits the broad
+    // want - has to move it across to the narrow one
+
+    #[test]
+    fn delivery_order_is_resolved_by_reassignment() {
+        let wants = vec![
+            want(
+                COLLECTION,
+                1,
+                Some(AllowedIds::Set(vec![
+                    NonFungibleLocalId::integer(1),
+                    NonFungibleLocalId::integer(2),
+                ])),
+            ),
+            want(
+                COLLECTION,
+                1,
+                Some(AllowedIds::Set(vec![NonFungibleLocalId::integer(1)])),
+            ),
+        ];
+
+        let delivered = vec![nft(COLLECTION, 1), nft(COLLECTION, 2)];
+        assert!(match_nft_deposit(&[], &wants, &delivered).is_empty());
+
+        let delivered = vec![nft(COLLECTION, 2), nft(COLLECTION, 1)];
+        assert!(match_nft_deposit(&[], &wants, &delivered).is_empty());
+
+        let delivered = vec![nft(COLLECTION, 2), nft(COLLECTION, 3)];
+        let mismatch = match_nft_deposit(&[], &wants, &delivered);
+        assert_eq!(mismatch.extra, vec![nft(COLLECTION, 3)]);
+        assert_eq!(mismatch.unmet_wants, vec![wants[1].clone()]);
+    }
+}
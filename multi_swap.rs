@@ -0,0 +1,331 @@
+use crate::barter_olc::{
+    asset_lists, is_expired, is_non_fungible, nft_global_ids, require_status, take_required_tokens,
+    Expiry, TradeStatus,
+};
+use crate::events::*;
+use crate::matching::assert_nft_deposit_matches;
+use crate::validation::{is_non_fungible_resource, TradeLimits};
+use crate::vault_store::VaultStore;
+use scrypto::prelude::*;
+
+// OVERVIEW OF BLUEPRINT
+
+// A circular swap between three or more participants - Alice wants Bob's item, Bob wants Carol's and Carol
+// wants Alice's. It generalises the A/B structure of Barter to any number of legs.
+
+// The swap is created with one leg per participant, in the order of the circle. Participant i deposits leg i and
+// receives the leg of the participant after them, with the last participant receiving the first leg. Creating
+// the swap mints one key per participant, returned in the same order for the creator to hand out.
+
+// Each participant deposits their whole leg in one call with a proof of their key, naming their position in the
+// swap - one account may hold several keys, so the position says which of them it is acting for. Nothing is
+// released until every leg is funded - the deposit that funds the last leg settles the swap, after which each
+// participant claims the leg they receive. Until then any participant can withdraw their leg, and the others
+// wait for it to be deposited again.
+
+// Fees and royalties are not applied to multi-party swaps yet.
+
+#[blueprint]
+mod multi_swap {
+
+    struct MultiSwap {
+        legs: Vec<SwapLeg>,
+        leg_vaults: KeyValueStore<u32, VaultStore>,
+        funded: Vec<bool>,
+        claimed: Vec<bool>,
+        key_resource: ResourceAddress,
+        key_ids: Vec<NonFungibleLocalId>,
+        badge_sweeper: Vault,
+        status: TradeStatus,
+        expiry: Option<Expiry>,
+    }
+
+    impl MultiSwap {
+        pub fn instantiate_multi_swap(
+            custom_swap_name: String,
+            legs: Vec<SwapLeg>,
+            expiry: Option<Expiry>,
+        ) -> (ComponentAddress, Vec<Bucket>) {
+            validate_legs(&legs);
+
+            if let Some(expiry) = &expiry {
+                assert!(!expiry.has_passed(), "Swap expiry must be in the future");
+            }
+
+            let badge_sweeper: Bucket = ResourceBuilder::new_fungible()
+                .divisibility(DIVISIBILITY_NONE)
+                .mint_initial_supply(1);
+
+            let key_resource = ResourceBuilder::new_uuid_non_fungible::<SwapKey>()
+                .metadata("name", "TROVE swap: ".to_string() + &custom_swap_name)
+                .metadata("description", "Your leg of a multi-party swap on trove.eco")
+                .mintable(rule!(require(badge_sweeper.resource_address())), LOCKED)
+                .burnable(rule!(require(badge_sweeper.resource_address())), LOCKED)
+                .create_with_no_initial_supply();
+
+            // each key records what its holder gives and what they receive
+
+            let mut keys: Vec<Bucket> = Vec::new();
+            let mut key_ids: Vec<NonFungibleLocalId> = Vec::new();
+
+            for (participant, leg) in legs.iter().enumerate() {
+                let receives = &legs[(participant + 1) % legs.len()];
+
+                let key: Bucket = badge_sweeper.authorize(|| {
+                    borrow_resource_manager!(key_resource).mint_uuid_non_fungible(SwapKey {
+                        participant: participant as u32,
+                        gives: leg.clone(),
+                        receives: receives.clone(),
+                    })
+                });
+
+                key_ids.push(key.non_fungible_local_id());
+                keys.push(key);
+            }
+
+            let leg_vaults: KeyValueStore<u32, VaultStore> = KeyValueStore::new();
+            for participant in 0..legs.len() {
+                leg_vaults.insert(participant as u32, VaultStore::new());
+            }
+
+            // every method checks the caller's key itself, so they are all open at the system level
+
+            let rules = AccessRulesConfig::new().default(AccessRule::AllowAll, AccessRule::DenyAll);
+
+            let component = Self {
+                funded: vec![false; legs.len()],
+                claimed: vec![false; legs.len()],
+                legs,
+                leg_vaults,
+                key_resource,
+                key_ids,
+                badge_sweeper: Vault::with_bucket(badge_sweeper),
+                status: TradeStatus::Open,
+                expiry,
+            }
+            .instantiate();
+            let component_address = component.globalize_with_access_rules(rules);
+
+            (component_address, keys)
+        }
+
+        // The leg must be deposited whole - every NFT it lists and at least each token amount. Any excess
+        // tokens are returned as change.
+
+        pub fn deposit_leg(
+            &mut self,
+            key: Proof,
+            participant: u32,
+            assets: Vec<Bucket>,
+        ) -> Vec<Bucket> {
+            self.require_status(&[TradeStatus::Open], "deposit a leg");
+            assert!(!self.is_expired(), "This swap has expired");
+
+            let participant = self.participant(key, participant);
+            assert!(
+                !self.funded[participant],
+                "Participant {} has already deposited their leg",
+                participant
+            );
+
+            let leg = self.legs[participant].clone();
+
+            let (nft_assets, token_assets): (Vec<Bucket>, Vec<Bucket>) =
+                assets.into_iter().partition(is_non_fungible);
+
+            let mut delivered: Vec<NonFungibleGlobalId> = Vec::new();
+            for bucket in nft_assets.iter() {
+                delivered.extend(nft_global_ids(bucket));
+            }
+            assert_nft_deposit_matches(&leg.nfts, &[], &delivered);
+
+            let (payment, change) = take_required_tokens(token_assets, &leg.tokens);
+
+            let mut vaults = self.leg_vaults.get_mut(&(participant as u32)).unwrap();
+            for bucket in nft_assets.into_iter().chain(payment.into_values()) {
+                vaults.put(bucket);
+            }
+            drop(vaults);
+
+            self.funded[participant] = true;
+
+            Runtime::emit_event(SwapLegDepositedEvent {
+                component: Runtime::global_address(),
+                participant: participant as u32,
+                nfts: leg.nfts,
+                tokens: leg.tokens,
+            });
+
+            if self.funded.iter().all(|funded| *funded) {
+                self.status = TradeStatus::Settled;
+            }
+
+            change
+        }
+
+        // Allowed until every leg is funded, including after the expiry has passed.
+
+        pub fn withdraw_leg(&mut self, key: Proof, participant: u32) -> Vec<Bucket> {
+            self.require_status(&[TradeStatus::Open], "withdraw a leg");
+
+            let participant = self.participant(key, participant);
+            assert!(
+                self.funded[participant],
+                "Participant {} has not deposited their leg",
+                participant
+            );
+
+            self.funded[participant] = false;
+
+            let buckets = self
+                .leg_vaults
+                .get_mut(&(participant as u32))
+                .unwrap()
+                .take_all();
+            let (nfts, tokens) = asset_lists(&buckets);
+
+            Runtime::emit_event(SwapLegWithdrawnEvent {
+                component: Runtime::global_address(),
+                participant: participant as u32,
+                nfts,
+                tokens,
+            });
+
+            buckets
+        }
+
+        // each participant receives the leg of the participant after them in the circle
+
+        pub fn claim_leg(&mut self, key: Proof, participant: u32) -> Vec<Bucket> {
+            self.require_status(&[TradeStatus::Settled], "claim a leg");
+
+            let participant = self.participant(key, participant);
+            assert!(
+                !self.claimed[participant],
+                "Participant {} has already claimed",
+                participant
+            );
+
+            self.claimed[participant] = true;
+
+            let source = ((participant + 1) % self.legs.len()) as u32;
+            let buckets = self.leg_vaults.get_mut(&source).unwrap().take_all();
+            let (nfts, tokens) = asset_lists(&buckets);
+
+            Runtime::emit_event(SwapLegClaimedEvent {
+                component: Runtime::global_address(),
+                participant: participant as u32,
+                nfts,
+                tokens,
+            });
+
+            buckets
+        }
+
+        pub fn legs(&self) -> Vec<SwapLeg> {
+            self.legs.clone()
+        }
+
+        pub fn funded(&self) -> Vec<bool> {
+            self.funded.clone()
+        }
+
+        pub fn status(&self) -> TradeStatus {
+            self.status
+        }
+
+        // checks the proof holds the key of the participant at this position in the swap
+
+        fn participant(&self, key: Proof, participant: u32) -> usize {
+            let key_id = self
+                .key_ids
+                .get(participant as usize)
+                .expect("No participant at this position in the swap")
+                .clone();
+
+            let validated = key
+                .validate_proof(ProofValidationMode::ValidateContainsNonFungible(
+                    NonFungibleGlobalId::new(self.key_resource, key_id),
+                ))
+                .expect("Proof does not hold this participant's key");
+            validated.drop();
+
+            participant as usize
+        }
+
+        fn require_status(&self, allowed: &[TradeStatus], action: &str) {
+            require_status(self.status, allowed, action, "swap");
+        }
+
+        fn is_expired(&self) -> bool {
+            is_expired(&self.expiry)
+        }
+    }
+}
+
+// what one participant gives - exact NFTs and token amounts
+
+#[derive(ScryptoSbor, Clone, Debug, PartialEq, Eq)]
+pub struct SwapLeg {
+    pub nfts: Vec<NonFungibleGlobalId>,
+    pub tokens: HashMap<ResourceAddress, Decimal>,
+}
+
+#[derive(NonFungibleData, ScryptoSbor, Debug)]
+struct SwapKey {
+    participant: u32,
+    gives: SwapLeg,
+    receives: SwapLeg,
+}
+
+// At least three legs, each giving something, no NFT listed twice across the swap and every leg within the
+// trade size limits - the same limits Barter applies to a side.
+
+fn validate_legs(legs: &[SwapLeg]) {
+    assert!(
+        legs.len() >= 3,
+        "A multi-party swap needs at least three participants, use Barter for two"
+    );
+
+    let limits = TradeLimits::default();
+    let mut listed_nfts: BTreeSet<&NonFungibleGlobalId> = BTreeSet::new();
+
+    for (participant, leg) in legs.iter().enumerate() {
+        assert!(
+            !leg.nfts.is_empty() || !leg.tokens.is_empty(),
+            "Leg {} gives nothing",
+            participant
+        );
+
+        for nft in leg.nfts.iter() {
+            assert!(
+                listed_nfts.insert(nft),
+                "NFT {:?} is listed more than once in the swap",
+                nft
+            );
+            assert!(
+                is_non_fungible_resource(nft.resource_address()),
+                "NFT {:?} in leg {} is not from a non-fungible resource",
+                nft,
+                participant
+            );
+        }
+
+        for (resource_address, amount) in leg.tokens.iter() {
+            assert!(
+                *amount > Decimal::zero(),
+                "Amount of {:?} in leg {} must be greater than zero",
+                resource_address,
+                participant
+            );
+            assert!(
+                !is_non_fungible_resource(*resource_address),
+                "Token {:?} in leg {} is non-fungible, list it by NFT id instead",
+                resource_address,
+                participant
+            );
+        }
+
+        limits.check_side(&format!("Leg {}", participant), &leg.nfts, &leg.tokens);
+    }
+}
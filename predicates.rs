@@ -0,0 +1,301 @@
+use scrypto::prelude::*;
+
+// NFT FIELD PREDICATES
+
+// Lets a collection want ask for NFTs by what their data says rather than by id - "a Phone whose name is
+// 'Real Scorpian'" or "an NFT whose rarity is at least 3". Each deposited NFT's data is read from its resource
+// and decoded as a plain SBOR value, so any collection's NonFungibleData can be checked without knowing its type.
+
+// Encoded NFT data holds its fields in declaration order without their names, and a blueprint can't read a
+// collection's data schema, so a predicate names its field by position only - e.g. name is field 0 of the
+// Phone data in bootstrap.rs. It carries no field name, so the Escroceipt never shows a name that wasn't checked.
+
+#[derive(ScryptoSbor, Clone, Debug, PartialEq, Eq)]
+pub struct FieldPredicate {
+    pub index: u32,
+    pub condition: FieldCondition,
+}
+
+#[derive(ScryptoSbor, Clone, Debug, PartialEq, Eq)]
+pub enum FieldCondition {
+    Equals(FieldValue),
+    GreaterThan(Decimal),
+    AtLeast(Decimal),
+    LessThan(Decimal),
+    AtMost(Decimal),
+    StartsWith(String),
+}
+
+#[derive(ScryptoSbor, Clone, Debug, PartialEq, Eq)]
+pub enum FieldValue {
+    Text(String),
+    Number(Decimal),
+    Bool(bool),
+}
+
+impl FieldPredicate {
+    // a field that is missing, or of a kind the condition can't compare, never matches
+
+    pub fn matches(&self, fields: &[ScryptoValue]) -> bool {
+        let value = match fields.get(self.index as usize) {
+            Some(value) => value,
+            None => return false,
+        };
+
+        match &self.condition {
+            FieldCondition::Equals(FieldValue::Text(text)) => {
+                matches!(value, Value::String { value } if value == text)
+            }
+            FieldCondition::Equals(FieldValue::Bool(flag)) => {
+                matches!(value, Value::Bool { value } if value == flag)
+            }
+            FieldCondition::Equals(FieldValue::Number(number)) => {
+                as_number(value) == Some(*number)
+            }
+            FieldCondition::GreaterThan(bound) => {
+                matches!(as_number(value), Some(n) if n > *bound)
+            }
+            FieldCondition::AtLeast(bound) => {
+                matches!(as_number(value), Some(n) if n >= *bound)
+            }
+            FieldCondition::LessThan(bound) => {
+                matches!(as_number(value), Some(n) if n < *bound)
+            }
+            FieldCondition::AtMost(bound) => {
+                matches!(as_number(value), Some(n) if n <= *bound)
+            }
+            FieldCondition::StartsWith(prefix) => {
+                matches!(value, Value::String { value } if value.starts_with(prefix.as_str()))
+            }
+        }
+    }
+
+    // Checks the predicate can ever match data shaped like `fields` - the field exists and holds a value of the
+    // kind its condition compares. Used on a sample NFT when a want is proposed.
+
+    pub fn check_fields(&self, fields: &[ScryptoValue]) -> Result<(), String> {
+        let value = fields.get(self.index as usize).ok_or_else(|| {
+            format!(
+                "NFT data has {} fields, so there is no field {}",
+                fields.len(),
+                self.index
+            )
+        })?;
+
+        let fits = match &self.condition {
+            FieldCondition::Equals(FieldValue::Text(_)) | FieldCondition::StartsWith(_) => {
+                matches!(value, Value::String { .. })
+            }
+            FieldCondition::Equals(FieldValue::Bool(_)) => matches!(value, Value::Bool { .. }),
+            FieldCondition::Equals(FieldValue::Number(_))
+            | FieldCondition::GreaterThan(_)
+            | FieldCondition::AtLeast(_)
+            | FieldCondition::LessThan(_)
+            | FieldCondition::AtMost(_) => as_number(value).is_some(),
+        };
+
+        if fits {
+            Ok(())
+        } else {
+            Err(format!(
+                "Field {} holds {:?}, which {:?} can't compare",
+                self.index, value, self.condition
+            ))
+        }
+    }
+}
+
+// integers of any width and Decimals compare as numbers
+
+fn as_number(value: &ScryptoValue) -> Option<Decimal> {
+    match value {
+        Value::U8 { value } => Some(Decimal::from(*value)),
+        Value::U16 { value } => Some(Decimal::from(*value)),
+        Value::U32 { value } => Some(Decimal::from(*value)),
+        Value::U64 { value } => Some(Decimal::from(*value)),
+        Value::U128 { value } => Some(Decimal::from(*value)),
+        Value::I8 { value } => Some(Decimal::from(*value)),
+        Value::I16 { value } => Some(Decimal::from(*value)),
+        Value::I32 { value } => Some(Decimal::from(*value)),
+        Value::I64 { value } => Some(Decimal::from(*value)),
+        Value::I128 { value } => Some(Decimal::from(*value)),
+        Value::Custom {
+            value: ScryptoCustomValue::Decimal(value),
+        } => Some(*value),
+        _ => None,
+    }
+}
+
+// The fields of an NFT's data, in declaration order. Data that isn't a struct has no fields to match.
+
+pub fn nft_fields(nft: &NonFungibleGlobalId) -> Vec<ScryptoValue> {
+    let data: NftData =
+        borrow_resource_manager!(nft.resource_address()).get_non_fungible_data(nft.local_id());
+
+    match data.0 {
+        Value::Tuple { fields } => fields,
+        _ => Vec::new(),
+    }
+}
+
+// Reads any NonFungibleData as an untyped SBOR value, by passing encoding and decoding straight through to it.
+
+struct NftData(ScryptoValue);
+
+impl NonFungibleData for NftData {
+    const MUTABLE_FIELDS: &'static [&'static str] = &[];
+}
+
+impl<E: Encoder<ScryptoCustomValueKind>> Encode<ScryptoCustomValueKind, E> for NftData {
+    fn encode_value_kind(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.0.encode_value_kind(encoder)
+    }
+
+    fn encode_body(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.0.encode_body(encoder)
+    }
+}
+
+impl<D: Decoder<ScryptoCustomValueKind>> Decode<ScryptoCustomValueKind, D> for NftData {
+    fn decode_body_with_value_kind(
+        decoder: &mut D,
+        value_kind: ValueKind<ScryptoCustomValueKind>,
+    ) -> Result<Self, DecodeError> {
+        ScryptoValue::decode_body_with_value_kind(decoder, value_kind).map(NftData)
+    }
+}
+
+impl Describe<ScryptoCustomTypeKind<GlobalTypeId>> for NftData {
+    const TYPE_ID: GlobalTypeId =
+        <ScryptoValue as Describe<ScryptoCustomTypeKind<GlobalTypeId>>>::TYPE_ID;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn predicate(index: u32, condition: FieldCondition) -> FieldPredicate {
+        FieldPredicate { index, condition }
+    }
+
+    fn text(value: &str) -> ScryptoValue {
+        Value::String {
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn every_integer_width_compares_as_a_number() {
+        let values = vec![
+            Value::U8 { value: 3 },
+            Value::U16 { value: 3 },
+            Value::U32 { value: 3 },
+            Value::U64 { value: 3 },
+            Value::U128 { value: 3 },
+            Value::I8 { value: 3 },
+            Value::I16 { value: 3 },
+            Value::I32 { value: 3 },
+            Value::I64 { value: 3 },
+            Value::I128 { value: 3 },
+        ];
+
+        for value in values {
+            let fields = vec![value];
+            assert!(
+                predicate(0, FieldCondition::Equals(FieldValue::Number(dec!("3"))))
+                    .matches(&fields)
+            );
+            assert!(predicate(0, FieldCondition::AtLeast(dec!("3"))).matches(&fields));
+            assert!(predicate(0, FieldCondition::AtMost(dec!("3"))).matches(&fields));
+            assert!(!predicate(0, FieldCondition::GreaterThan(dec!("3"))).matches(&fields));
+            assert!(!predicate(0, FieldCondition::LessThan(dec!("3"))).matches(&fields));
+        }
+    }
+
+    #[test]
+    fn negative_integers_compare_below_zero() {
+        let fields = vec![Value::I32 { value: -2 }];
+
+        assert!(predicate(0, FieldCondition::LessThan(Decimal::zero())).matches(&fields));
+        assert!(!predicate(0, FieldCondition::AtLeast(Decimal::zero())).matches(&fields));
+    }
+
+    #[test]
+    fn decimal_fields_compare_as_numbers() {
+        let fields = vec![Value::Custom {
+            value: ScryptoCustomValue::Decimal(dec!("2.5")),
+        }];
+
+        assert!(predicate(0, FieldCondition::GreaterThan(dec!("2"))).matches(&fields));
+        assert!(predicate(0, FieldCondition::LessThan(dec!("3"))).matches(&fields));
+        assert!(
+            predicate(0, FieldCondition::Equals(FieldValue::Number(dec!("2.5")))).matches(&fields)
+        );
+    }
+
+    #[test]
+    fn text_fields_match_exactly_or_by_prefix() {
+        let fields = vec![text("Real Scorpian")];
+
+        assert!(predicate(
+            0,
+            FieldCondition::Equals(FieldValue::Text("Real Scorpian".to_string()))
+        )
+        .matches(&fields));
+        assert!(!predicate(
+            0,
+            FieldCondition::Equals(FieldValue::Text("Real".to_string()))
+        )
+        .matches(&fields));
+        assert!(predicate(0, FieldCondition::StartsWith("Real".to_string())).matches(&fields));
+        assert!(!predicate(0, FieldCondition::StartsWith("Scorpian".to_string())).matches(&fields));
+    }
+
+    #[test]
+    fn bool_fields_match_by_equality() {
+        let fields = vec![Value::Bool { value: true }];
+
+        assert!(predicate(0, FieldCondition::Equals(FieldValue::Bool(true))).matches(&fields));
+        assert!(!predicate(0, FieldCondition::Equals(FieldValue::Bool(false))).matches(&fields));
+    }
+
+    // a number stored as text, or text compared as a number, is the wrong kind and never matches
+
+    #[test]
+    fn mismatched_kinds_never_match() {
+        let fields = vec![text("3"), Value::U8 { value: 3 }];
+
+        assert!(!predicate(0, FieldCondition::AtLeast(dec!("3"))).matches(&fields));
+        assert!(!predicate(1, FieldCondition::StartsWith("3".to_string())).matches(&fields));
+        assert!(!predicate(1, FieldCondition::Equals(FieldValue::Bool(true))).matches(&fields));
+    }
+
+    #[test]
+    fn missing_field_never_matches() {
+        let fields = vec![Value::U8 { value: 3 }];
+
+        assert!(!predicate(1, FieldCondition::AtLeast(Decimal::zero())).matches(&fields));
+        assert!(!predicate(0, FieldCondition::AtLeast(Decimal::zero())).matches(&[]));
+    }
+
+    #[test]
+    fn check_fields_rejects_missing_fields_and_wrong_kinds() {
+        let fields = vec![text("Real Scorpian"), Value::U8 { value: 3 }];
+
+        assert!(predicate(0, FieldCondition::StartsWith("Real".to_string()))
+            .check_fields(&fields)
+            .is_ok());
+        assert!(predicate(1, FieldCondition::AtLeast(dec!("1")))
+            .check_fields(&fields)
+            .is_ok());
+        assert!(predicate(2, FieldCondition::AtLeast(dec!("1")))
+            .check_fields(&fields)
+            .is_err());
+        assert!(predicate(0, FieldCondition::AtLeast(dec!("1")))
+            .check_fields(&fields)
+            .is_err());
+        assert!(predicate(1, FieldCondition::Equals(FieldValue::Bool(true)))
+            .check_fields(&fields)
+            .is_err());
+    }
+}
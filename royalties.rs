@@ -0,0 +1,111 @@
+use scrypto::prelude::*;
+
+// NFT CREATOR ROYALTIES
+
+// Creators set two metadata fields on their collection to take a royalty on TROVE trades:
+//   royalty_percentage - percentage of the sale price, e.g. "5" or "2.5"
+//   royalty_account    - hex encoded address of the account the royalty is paid to
+// Only whoever controls the collection's metadata can set these, so they are taken as the creator's terms.
+// Collections without both fields, or with values that can't be read, carry no royalty.
+
+// The sale price of the NFTs on one side of a trade is the fungible side offered against them. Each collection
+// takes its percentage of that price in proportion to how many of the side's NFTs it makes up, and the seller
+// pays it out of what they receive. The shares are worked out when the proposal is created and recorded on the
+// Escroceipt, so both parties can see them before committing.
+
+// Barter-only trades, where the other side has no fungibles, have no price to take a royalty from and pay none.
+
+#[derive(ScryptoSbor, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TradeSide {
+    A,
+    B,
+}
+
+#[derive(ScryptoSbor, Clone, Debug, PartialEq, Eq)]
+pub struct RoyaltyShare {
+    pub collection: ResourceAddress,
+    pub recipient: ComponentAddress,
+    pub resource: ResourceAddress,
+    pub amount: Decimal,
+    // the side whose fungibles the royalty is taken from
+    pub paid_from: TradeSide,
+}
+
+pub fn royalty_shares(
+    nfts: &[NonFungibleGlobalId],
+    consideration: &HashMap<ResourceAddress, Decimal>,
+    paid_from: TradeSide,
+) -> Vec<RoyaltyShare> {
+    let mut per_collection: BTreeMap<ResourceAddress, usize> = BTreeMap::new();
+    for nft in nfts.iter() {
+        *per_collection.entry(nft.resource_address()).or_insert(0) += 1;
+    }
+
+    collection_royalty_shares(&per_collection, consideration, paid_from)
+}
+
+// Same split from a count of NFTs per collection, for sides that want NFTs by collection rather than by id.
+
+pub fn collection_royalty_shares(
+    per_collection: &BTreeMap<ResourceAddress, usize>,
+    consideration: &HashMap<ResourceAddress, Decimal>,
+    paid_from: TradeSide,
+) -> Vec<RoyaltyShare> {
+    let mut shares: Vec<RoyaltyShare> = Vec::new();
+
+    let total: usize = per_collection.values().sum();
+
+    if total == 0 || consideration.is_empty() {
+        return shares;
+    }
+
+    let total = Decimal::from(total);
+
+    for (collection, count) in per_collection.iter() {
+        let (percentage, recipient) = match creator_royalty(*collection) {
+            Some(terms) => terms,
+            None => continue,
+        };
+
+        for (resource, price) in consideration.iter() {
+            let amount = *price * percentage / dec!("100") * Decimal::from(*count) / total;
+            let amount = round_down_to_divisibility(*resource, amount);
+
+            if amount > Decimal::zero() {
+                shares.push(RoyaltyShare {
+                    collection: *collection,
+                    recipient,
+                    resource: *resource,
+                    amount,
+                    paid_from,
+                });
+            }
+        }
+    }
+
+    shares
+}
+
+fn creator_royalty(collection: ResourceAddress) -> Option<(Decimal, ComponentAddress)> {
+    let manager = borrow_resource_manager!(collection);
+
+    let percentage = Decimal::from_str(&manager.get_metadata("royalty_percentage")?).ok()?;
+    let recipient = ComponentAddress::try_from_hex(&manager.get_metadata("royalty_account")?)?;
+
+    if percentage <= Decimal::zero() || percentage > dec!("100") {
+        return None;
+    }
+
+    Some((percentage, recipient))
+}
+
+// rounded down so a royalty never asks for more than the price can be split into
+
+fn round_down_to_divisibility(resource_address: ResourceAddress, amount: Decimal) -> Decimal {
+    match borrow_resource_manager!(resource_address).resource_type() {
+        ResourceType::Fungible { divisibility } => {
+            amount.round(divisibility, RoundingMode::TowardsNegativeInfinity)
+        }
+        _ => amount,
+    }
+}
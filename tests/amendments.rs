@@ -0,0 +1,188 @@
+mod common;
+
+use common::*;
+use radix_engine::transaction::TransactionReceipt;
+use scrypto::prelude::*;
+use scrypto_unit::*;
+use transaction::builder::ManifestBuilder;
+
+// Amendments to an open proposal, made by A with a proof of their key
+
+struct Setup {
+    test_runner: TestRunner,
+    a: Party,
+    trade: Trade,
+    tokens: Vec<ResourceAddress>,
+}
+
+// A offers 100 each of two fresh tokens for 10 XRD
+
+fn setup() -> Setup {
+    let mut test_runner = TestRunner::builder().build();
+    let a = Party::new(&mut test_runner);
+    let protocol = Protocol::without_fee(&mut test_runner, &a);
+
+    let tokens = vec![
+        test_runner.create_fungible_resource(dec!("100"), 18, a.account),
+        test_runner.create_fungible_resource(dec!("100"), 18, a.account),
+    ];
+
+    let receipt = propose_assets(
+        &mut test_runner,
+        &protocol,
+        &a,
+        &[],
+        &tokens,
+        BTreeMap::from([(RADIX_TOKEN, dec!("10"))]),
+        TradeOptions::with_expiry(None),
+    );
+    let trade = trade_from_receipt(&mut test_runner, &receipt, &a);
+
+    Setup {
+        test_runner,
+        a,
+        trade,
+        tokens,
+    }
+}
+
+fn withdraw_offered(
+    setup: &mut Setup,
+    tokens: BTreeMap<ResourceAddress, Decimal>,
+) -> TransactionReceipt {
+    let mut builder = ManifestBuilder::new();
+    builder
+        .create_proof_from_account(setup.a.account, setup.trade.a_key)
+        .call_method(
+            setup.trade.component,
+            "withdraw_offered_assets",
+            manifest_args!(Vec::<NonFungibleGlobalId>::new(), tokens),
+        );
+
+    call_as(&mut setup.test_runner, &setup.a, &mut builder)
+}
+
+fn unclaimed_assets(setup: &mut Setup) -> (Vec<ResourceAddress>, Vec<ResourceAddress>) {
+    let manifest = ManifestBuilder::new()
+        .call_method(setup.trade.component, "unclaimed_assets", manifest_args!())
+        .build();
+
+    let receipt = setup
+        .test_runner
+        .execute_manifest_ignoring_fee(manifest, setup.a.signer());
+    receipt.expect_commit_success().output(0)
+}
+
+#[test]
+fn withdrawing_part_of_a_resource_keeps_it_listed() {
+    let mut setup = setup();
+    let token = setup.tokens[1];
+
+    withdraw_offered(&mut setup, BTreeMap::from([(token, dec!("40"))])).expect_commit_success();
+
+    let (a_side, _) = unclaimed_assets(&mut setup);
+    assert_eq!(a_side, setup.tokens);
+}
+
+#[test]
+fn withdrawing_all_of_a_resource_drops_it_from_unclaimed_assets() {
+    let mut setup = setup();
+    let token = setup.tokens[1];
+
+    withdraw_offered(&mut setup, BTreeMap::from([(token, dec!("100"))])).expect_commit_success();
+
+    let (a_side, _) = unclaimed_assets(&mut setup);
+    assert_eq!(a_side, vec![setup.tokens[0]]);
+}
+
+#[test]
+fn withdrawing_everything_offered_is_rejected() {
+    let mut setup = setup();
+    let withdrawn = setup
+        .tokens
+        .iter()
+        .map(|token| (*token, dec!("100")))
+        .collect();
+
+    withdraw_offered(&mut setup, withdrawn).expect_commit_failure();
+}
+
+// Every method that commits B to A's terms takes the revision B was shown. After A amends the ask from 10 to
+// 12 XRD, calls made with the old revision fail and the same calls with the new one go through.
+
+fn amend_ask_to_12_xrd(setup: &mut Setup) {
+    let mut builder = ManifestBuilder::new();
+    builder
+        .create_proof_from_account(setup.a.account, setup.trade.a_key)
+        .call_method(
+            setup.trade.component,
+            "amend_ask",
+            manifest_args!(
+                Vec::<NonFungibleGlobalId>::new(),
+                Vec::<CollectionWant>::new(),
+                BTreeMap::from([(RADIX_TOKEN, dec!("12"))])
+            ),
+        );
+
+    call_as(&mut setup.test_runner, &setup.a, &mut builder).expect_commit_success();
+}
+
+fn taker_call(
+    setup: &mut Setup,
+    taker: &Party,
+    method: &str,
+    revision: u64,
+) -> TransactionReceipt {
+    let mut builder = ManifestBuilder::new();
+    builder
+        .withdraw_from_account(taker.account, RADIX_TOKEN, dec!("12"))
+        .take_from_worktop(RADIX_TOKEN, |builder, bucket| {
+            let args = match method {
+                "accept" => manifest_args!(vec![bucket], revision),
+                "fill_partial" => manifest_args!(vec![bucket], dec!("0.5"), revision),
+                _ => manifest_args!(
+                    vec![bucket],
+                    Option::<ManifestProof>::None,
+                    taker.account,
+                    revision
+                ),
+            };
+            builder.call_method(setup.trade.component, method, args)
+        });
+
+    call_as(&mut setup.test_runner, taker, &mut builder)
+}
+
+#[test]
+fn accept_on_a_stale_revision_is_rejected() {
+    let mut setup = setup();
+    let b = Party::new(&mut setup.test_runner);
+    amend_ask_to_12_xrd(&mut setup);
+
+    taker_call(&mut setup, &b, "accept", 0).expect_commit_failure();
+    taker_call(&mut setup, &b, "accept", 1).expect_commit_success();
+}
+
+#[test]
+fn deposit_on_a_stale_revision_is_rejected() {
+    let mut setup = setup();
+    let b = Party::new(&mut setup.test_runner);
+    amend_ask_to_12_xrd(&mut setup);
+
+    taker_call(&mut setup, &b, "b_deposit_tokens", 0).expect_commit_failure();
+    taker_call(&mut setup, &b, "b_deposit_tokens", 1).expect_commit_success();
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Filled
+    );
+}
+
+#[test]
+fn partial_fill_on_a_stale_revision_is_rejected() {
+    let mut setup = setup();
+    let b = Party::new(&mut setup.test_runner);
+    amend_ask_to_12_xrd(&mut setup);
+
+    taker_call(&mut setup, &b, "fill_partial", 0).expect_commit_failure();
+    taker_call(&mut setup, &b, "fill_partial", 1).expect_commit_success();
+}
@@ -0,0 +1,185 @@
+mod common;
+
+use common::*;
+use scrypto::prelude::*;
+use scrypto_unit::*;
+use transaction::builder::ManifestBuilder;
+
+// TRADE SIZE BENCHMARK
+
+// Measures how the cost of cancelling a trade grows with the number of distinct resources and NFTs A offers.
+// Cancel empties every vault on A's side in one call, the same withdraw loops settlement runs, so it is the
+// call that decides how large a side can safely be.
+
+// Not run with the rest of the tests. Run them with
+//     cargo test --release --test bench -- --ignored --nocapture --test-threads 1
+// and each table is printed and written to bench_output.txt and bench_layout_output.txt. Proposals above
+// TradeLimits::CEILING are rejected, so to measure larger sides raise the ceiling locally first.
+
+// bench_vault_layouts compares the HashMap of vaults the original blueprint kept with VaultStore, holding the
+// same assets in the VaultBench blueprint - the cost of adding one more resource, which only touches one
+// vault, and of emptying every vault.
+
+// (fungibles, collections, NFTs per collection)
+
+const SIZES: [(u32, u32, u32); 7] = [
+    (1, 0, 0),
+    (5, 0, 0),
+    (10, 0, 0),
+    (0, 1, 10),
+    (0, 1, 50),
+    (0, 10, 5),
+    (10, 10, 5),
+];
+
+#[test]
+#[ignore]
+fn bench_cancel_cost_by_trade_size() {
+    let mut lines: Vec<String> = vec![format!(
+        "{:>10} {:>12} {:>6} {:>16}",
+        "fungibles", "collections", "nfts", "cancel cost units"
+    )];
+
+    for (token_count, collection_count, nfts_per_collection) in SIZES {
+        let mut test_runner = TestRunner::builder().build();
+        let a = Party::new(&mut test_runner);
+        let protocol = Protocol::without_fee(&mut test_runner, &a);
+
+        let (tokens, collections) = bench_assets(
+            &mut test_runner,
+            &protocol,
+            &a,
+            token_count,
+            collection_count,
+            nfts_per_collection,
+            None,
+        );
+
+        let receipt = propose_assets(
+            &mut test_runner,
+            &protocol,
+            &a,
+            &collections,
+            &tokens,
+            BTreeMap::from([(RADIX_TOKEN, dec!("1"))]),
+            TradeOptions::with_expiry(None),
+        );
+        let trade = trade_from_receipt(&mut test_runner, &receipt, &a);
+
+        let receipt = cancel(&mut test_runner, &trade, &a, false);
+        receipt.expect_commit_success();
+
+        lines.push(format!(
+            "{:>10} {:>12} {:>6} {:>16}",
+            token_count,
+            collection_count,
+            collection_count * nfts_per_collection,
+            receipt.execution.fee_summary.cost_unit_consumed
+        ));
+    }
+
+    let table = lines.join("\n");
+    println!("{}", table);
+    std::fs::write("bench_output.txt", table + "\n").expect("Unable to write bench_output.txt");
+}
+
+#[derive(ManifestSbor, Clone, Copy)]
+enum VaultLayout {
+    HashMap,
+    VaultStore,
+}
+
+// cost units of VaultBench::put and VaultBench::take_all with the given assets in the given layout
+
+fn layout_costs(size: (u32, u32, u32), layout: VaultLayout) -> (u32, u32) {
+    let (token_count, collection_count, nfts_per_collection) = size;
+
+    let mut test_runner = TestRunner::builder().build();
+    let a = Party::new(&mut test_runner);
+    let protocol = Protocol::without_fee(&mut test_runner, &a);
+
+    let (tokens, collections) = bench_assets(
+        &mut test_runner,
+        &protocol,
+        &a,
+        token_count,
+        collection_count,
+        nfts_per_collection,
+        None,
+    );
+
+    let held = test_runner.get_component_resources(a.account);
+    let mut builder = ManifestBuilder::new();
+    let mut buckets: Vec<ManifestBucket> = Vec::new();
+    for resource_address in tokens.iter().chain(collections.iter()) {
+        builder
+            .withdraw_from_account(a.account, *resource_address, held[resource_address])
+            .take_from_worktop(*resource_address, |builder, bucket| {
+                buckets.push(bucket);
+                builder
+            });
+    }
+    builder.call_function(
+        protocol.package,
+        "VaultBench",
+        "instantiate_vault_bench",
+        manifest_args!(buckets, layout),
+    );
+    let receipt = call_as(&mut test_runner, &a, &mut builder);
+    let bench = receipt.expect_commit_success().new_component_addresses()[0];
+
+    let mut builder = ManifestBuilder::new();
+    builder
+        .withdraw_from_account(a.account, RADIX_TOKEN, dec!("1"))
+        .take_from_worktop(RADIX_TOKEN, |builder, bucket| {
+            builder.call_method(bench, "put", manifest_args!(bucket))
+        });
+    let receipt = call_as(&mut test_runner, &a, &mut builder);
+    receipt.expect_commit_success();
+    let put = receipt.execution.fee_summary.cost_unit_consumed;
+
+    let mut builder = ManifestBuilder::new();
+    builder.call_method(bench, "take_all", manifest_args!());
+    let receipt = call_as(&mut test_runner, &a, &mut builder);
+    receipt.expect_commit_success();
+    let take_all = receipt.execution.fee_summary.cost_unit_consumed;
+
+    (put, take_all)
+}
+
+#[test]
+#[ignore]
+fn bench_vault_layouts() {
+    let mut lines: Vec<String> = vec![format!(
+        "{:>10} {:>12} {:>6} {:>12} {:>12} {:>16} {:>16}",
+        "fungibles",
+        "collections",
+        "nfts",
+        "map put",
+        "store put",
+        "map take_all",
+        "store take_all"
+    )];
+
+    for size in SIZES {
+        let (token_count, collection_count, nfts_per_collection) = size;
+        let (map_put, map_take_all) = layout_costs(size, VaultLayout::HashMap);
+        let (store_put, store_take_all) = layout_costs(size, VaultLayout::VaultStore);
+
+        lines.push(format!(
+            "{:>10} {:>12} {:>6} {:>12} {:>12} {:>16} {:>16}",
+            token_count,
+            collection_count,
+            collection_count * nfts_per_collection,
+            map_put,
+            store_put,
+            map_take_all,
+            store_take_all
+        ));
+    }
+
+    let table = lines.join("\n");
+    println!("{}", table);
+    std::fs::write("bench_layout_output.txt", table + "\n")
+        .expect("Unable to write bench_layout_output.txt");
+}
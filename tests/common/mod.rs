@@ -0,0 +1,371 @@
+#![allow(dead_code)]
+
+use radix_engine::transaction::TransactionReceipt;
+use scrypto::prelude::*;
+use scrypto_unit::*;
+use transaction::builder::ManifestBuilder;
+
+// Manifest-side copies of the blueprint's argument and return types. SBOR encodes by shape, so these decode
+// as the originals as long as the variants and fields stay in the same order.
+
+#[derive(ManifestSbor)]
+pub enum Expiry {
+    AtTime(Instant),
+    AtEpoch(u64),
+}
+
+#[derive(ManifestSbor)]
+pub enum PermittedTaker {
+    Account(PublicKey),
+    Badge(ResourceAddress),
+    NonFungible(NonFungibleGlobalId),
+}
+
+#[derive(ManifestSbor, Clone, Copy)]
+pub struct TradeLimits {
+    pub max_resources: u32,
+    pub max_nfts: u32,
+    pub max_fungibles: u32,
+}
+
+// only ever sent empty, so its fields are never encoded
+
+#[derive(ManifestSbor)]
+pub struct CollectionWant {}
+
+#[derive(ManifestSbor)]
+pub struct TradeOptions {
+    pub expiry: Option<Expiry>,
+    pub permitted_takers: Vec<PermittedTaker>,
+    pub limits: Option<TradeLimits>,
+    pub collection_wants: Vec<CollectionWant>,
+}
+
+impl TradeOptions {
+    pub fn with_expiry(expiry: Option<Expiry>) -> Self {
+        Self {
+            expiry,
+            permitted_takers: Vec::new(),
+            limits: None,
+            collection_wants: Vec::new(),
+        }
+    }
+}
+
+#[derive(ManifestSbor)]
+pub enum ProtocolFee {
+    Flat(Decimal),
+    Percentage(Decimal),
+}
+
+#[derive(ScryptoSbor, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TradeStatus {
+    Open,
+    Filled,
+    Settled,
+    Cancelled,
+    Expired,
+}
+
+pub struct Party {
+    pub public_key: EcdsaSecp256k1PublicKey,
+    pub account: ComponentAddress,
+}
+
+impl Party {
+    pub fn new(test_runner: &mut TestRunner) -> Self {
+        let (public_key, _, account) = test_runner.new_allocated_account();
+        Self {
+            public_key,
+            account,
+        }
+    }
+
+    pub fn signer(&self) -> Vec<NonFungibleGlobalId> {
+        vec![NonFungibleGlobalId::from_public_key(&self.public_key)]
+    }
+}
+
+// The published package, with the fee collector and the hub every trade is created against. The fee admin
+// badge goes to `admin`.
+
+pub struct Protocol {
+    pub package: PackageAddress,
+    pub fee_collector: ComponentAddress,
+    pub hub: ComponentAddress,
+}
+
+impl Protocol {
+    pub fn new(test_runner: &mut TestRunner, admin: &Party, fee_terms: ProtocolFee) -> Self {
+        let package = test_runner.compile_and_publish(this_package!());
+
+        let mut builder = ManifestBuilder::new();
+        builder.call_function(
+            package,
+            "FeeCollector",
+            "instantiate_fee_collector",
+            manifest_args!(fee_terms),
+        );
+        let receipt = call_as(test_runner, admin, &mut builder);
+        let fee_collector = receipt.expect_commit_success().new_component_addresses()[0];
+
+        let mut builder = ManifestBuilder::new();
+        builder.call_function(
+            package,
+            "TroveHub",
+            "instantiate_trove_hub",
+            manifest_args!(fee_collector),
+        );
+        let receipt = call_as(test_runner, admin, &mut builder);
+        let hub = receipt.expect_commit_success().new_component_addresses()[0];
+
+        Self {
+            package,
+            fee_collector,
+            hub,
+        }
+    }
+
+    pub fn without_fee(test_runner: &mut TestRunner, admin: &Party) -> Self {
+        Self::new(test_runner, admin, ProtocolFee::Flat(Decimal::zero()))
+    }
+}
+
+pub struct Trade {
+    pub component: ComponentAddress,
+    pub a_key: ResourceAddress,
+    pub b_key: ResourceAddress,
+}
+
+// A offers `offered` of one token and asks for `ask` in return. Returns the receipt so callers can check a
+// proposal that should fail.
+
+pub fn propose(
+    test_runner: &mut TestRunner,
+    protocol: &Protocol,
+    a: &Party,
+    offered: (ResourceAddress, Decimal),
+    ask: BTreeMap<ResourceAddress, Decimal>,
+    options: TradeOptions,
+) -> TransactionReceipt {
+    let manifest = ManifestBuilder::new()
+        .withdraw_from_account(a.account, offered.0, offered.1)
+        .take_from_worktop(offered.0, |builder, bucket| {
+            builder.call_function(
+                protocol.package,
+                "Barter",
+                "lets_barter_partner_permitted_clock",
+                manifest_args!(
+                    "test trade".to_string(),
+                    Vec::<ManifestBucket>::new(),
+                    vec![bucket],
+                    Vec::<NonFungibleGlobalId>::new(),
+                    ask,
+                    options,
+                    protocol.hub,
+                    Option::<ManifestProof>::None
+                ),
+            )
+        })
+        .call_method(
+            a.account,
+            "deposit_batch",
+            manifest_args!(ManifestExpression::EntireWorktop),
+        )
+        .build();
+
+    test_runner.execute_manifest_ignoring_fee(manifest, a.signer())
+}
+
+// the A key is the new resource A now holds one of, the counterparty key is the other non-fungible minted
+// for the trade that nobody holds yet
+
+pub fn trade_from_receipt(
+    test_runner: &mut TestRunner,
+    receipt: &TransactionReceipt,
+    a: &Party,
+) -> Trade {
+    let commit = receipt.expect_commit_success();
+    let component = commit.new_component_addresses()[0];
+    let held = test_runner.get_component_resources(a.account);
+
+    let new_resources = commit.new_resource_addresses();
+    let a_key = *new_resources
+        .iter()
+        .find(|resource_address| held.get(*resource_address) == Some(&Decimal::one()))
+        .expect("A was not given a key");
+    let b_key = *new_resources
+        .last()
+        .expect("No counterparty key resource was created");
+
+    Trade {
+        component,
+        a_key,
+        b_key,
+    }
+}
+
+pub fn call_as(
+    test_runner: &mut TestRunner,
+    party: &Party,
+    builder: &mut ManifestBuilder,
+) -> TransactionReceipt {
+    let manifest = builder
+        .call_method(
+            party.account,
+            "deposit_batch",
+            manifest_args!(ManifestExpression::EntireWorktop),
+        )
+        .build();
+
+    test_runner.execute_manifest_ignoring_fee(manifest, party.signer())
+}
+
+// B pays the whole ask in one call with b_deposit_tokens, minting their counterparty key. Refunds go to B's
+// own account.
+
+pub fn deposit_tokens(
+    test_runner: &mut TestRunner,
+    trade: &Trade,
+    b: &Party,
+    payment: (ResourceAddress, Decimal),
+    revision: u64,
+) -> TransactionReceipt {
+    let mut builder = ManifestBuilder::new();
+    builder
+        .withdraw_from_account(b.account, payment.0, payment.1)
+        .take_from_worktop(payment.0, |builder, bucket| {
+            builder.call_method(
+                trade.component,
+                "b_deposit_tokens",
+                manifest_args!(
+                    vec![bucket],
+                    Option::<ManifestProof>::None,
+                    b.account,
+                    revision
+                ),
+            )
+        });
+
+    call_as(test_runner, b, &mut builder)
+}
+
+pub fn status(test_runner: &mut TestRunner, trade: &Trade, party: &Party) -> TradeStatus {
+    let manifest = ManifestBuilder::new()
+        .call_method(trade.component, "status", manifest_args!())
+        .build();
+
+    let receipt = test_runner.execute_manifest_ignoring_fee(manifest, party.signer());
+    receipt.expect_commit_success().output(0)
+}
+
+// Mints `token_count` tokens of 1000 each and `collection_count` collections of `nfts_per_collection` NFTs
+// into A's account with the bench bootstrap. With `royalty` set each collection carries that royalty. Returns
+// the token and collection addresses in that order.
+
+pub fn bench_assets(
+    test_runner: &mut TestRunner,
+    protocol: &Protocol,
+    a: &Party,
+    token_count: u32,
+    collection_count: u32,
+    nfts_per_collection: u32,
+    royalty: Option<(Decimal, ComponentAddress)>,
+) -> (Vec<ResourceAddress>, Vec<ResourceAddress>) {
+    let mut builder = ManifestBuilder::new();
+    builder.call_function(
+        protocol.package,
+        "Bootstrapbench",
+        "bootstrap_bench",
+        manifest_args!(token_count, collection_count, nfts_per_collection, royalty),
+    );
+    let receipt = call_as(test_runner, a, &mut builder);
+
+    let mut resources = receipt.expect_commit_success().new_resource_addresses().clone();
+    let collections = resources.split_off(token_count as usize);
+
+    (resources, collections)
+}
+
+// A offers the whole balance of every resource listed, NFTs and tokens, for `ask`
+
+pub fn propose_assets(
+    test_runner: &mut TestRunner,
+    protocol: &Protocol,
+    a: &Party,
+    nft_resources: &[ResourceAddress],
+    token_resources: &[ResourceAddress],
+    ask: BTreeMap<ResourceAddress, Decimal>,
+    options: TradeOptions,
+) -> TransactionReceipt {
+    let held = test_runner.get_component_resources(a.account);
+
+    let mut builder = ManifestBuilder::new();
+    let mut nft_buckets: Vec<ManifestBucket> = Vec::new();
+    let mut token_buckets: Vec<ManifestBucket> = Vec::new();
+
+    for resource_address in nft_resources.iter() {
+        builder
+            .withdraw_from_account(a.account, *resource_address, held[resource_address])
+            .take_from_worktop(*resource_address, |builder, bucket| {
+                nft_buckets.push(bucket);
+                builder
+            });
+    }
+    for resource_address in token_resources.iter() {
+        builder
+            .withdraw_from_account(a.account, *resource_address, held[resource_address])
+            .take_from_worktop(*resource_address, |builder, bucket| {
+                token_buckets.push(bucket);
+                builder
+            });
+    }
+
+    builder.call_function(
+        protocol.package,
+        "Barter",
+        "lets_barter_partner_permitted_clock",
+        manifest_args!(
+            "test trade".to_string(),
+            nft_buckets,
+            token_buckets,
+            Vec::<NonFungibleGlobalId>::new(),
+            ask,
+            options,
+            protocol.hub,
+            Option::<ManifestProof>::None
+        ),
+    );
+
+    call_as(test_runner, a, &mut builder)
+}
+
+// A cancels and takes back everything in one call. With `metered` the transaction locks a fee and runs under
+// the normal cost unit limit, otherwise the fee is ignored.
+
+pub fn cancel(
+    test_runner: &mut TestRunner,
+    trade: &Trade,
+    a: &Party,
+    metered: bool,
+) -> TransactionReceipt {
+    let mut builder = ManifestBuilder::new();
+    if metered {
+        builder.lock_fee(a.account, dec!("100"));
+    }
+    builder
+        .create_proof_from_account(a.account, trade.a_key)
+        .call_method(trade.component, "cancel", manifest_args!())
+        .call_method(
+            a.account,
+            "deposit_batch",
+            manifest_args!(ManifestExpression::EntireWorktop),
+        );
+    let manifest = builder.build();
+
+    if metered {
+        test_runner.execute_manifest(manifest, a.signer())
+    } else {
+        test_runner.execute_manifest_ignoring_fee(manifest, a.signer())
+    }
+}
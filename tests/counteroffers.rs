@@ -0,0 +1,227 @@
+mod common;
+
+use common::*;
+use radix_engine::transaction::TransactionReceipt;
+use scrypto::prelude::*;
+use scrypto_unit::*;
+use transaction::builder::ManifestBuilder;
+
+// Counteroffers made by B, and what A accepting, rejecting or B withdrawing them does to the trade
+
+struct Setup {
+    test_runner: TestRunner,
+    a: Party,
+    b: Party,
+    trade: Trade,
+    offered: ResourceAddress,
+}
+
+// A offers 100 of a fresh token for 10 XRD
+
+fn setup() -> Setup {
+    let mut test_runner = TestRunner::builder().build();
+    let a = Party::new(&mut test_runner);
+    let b = Party::new(&mut test_runner);
+    let protocol = Protocol::without_fee(&mut test_runner, &a);
+
+    let offered = test_runner.create_fungible_resource(dec!("100"), 18, a.account);
+
+    let receipt = propose(
+        &mut test_runner,
+        &protocol,
+        &a,
+        (offered, dec!("100")),
+        BTreeMap::from([(RADIX_TOKEN, dec!("10"))]),
+        TradeOptions::with_expiry(None),
+    );
+    let trade = trade_from_receipt(&mut test_runner, &receipt, &a);
+
+    Setup {
+        test_runner,
+        a,
+        b,
+        trade,
+        offered,
+    }
+}
+
+// B offers `amount` XRD instead, refunded to `refund_account` if A rejects it
+
+fn counter_offer(
+    setup: &mut Setup,
+    amount: Decimal,
+    refund_account: ComponentAddress,
+    revision: u64,
+) -> TransactionReceipt {
+    let mut builder = ManifestBuilder::new();
+    builder
+        .withdraw_from_account(setup.b.account, RADIX_TOKEN, amount)
+        .take_from_worktop(RADIX_TOKEN, |builder, bucket| {
+            builder.call_method(
+                setup.trade.component,
+                "counter_offer",
+                manifest_args!(
+                    vec![bucket],
+                    BTreeMap::from([(RADIX_TOKEN, amount)]),
+                    refund_account,
+                    revision
+                ),
+            )
+        });
+
+    call_as(&mut setup.test_runner, &setup.b, &mut builder)
+}
+
+fn as_a(setup: &mut Setup, method: &str, counter_id: u64) -> TransactionReceipt {
+    let mut builder = ManifestBuilder::new();
+    builder
+        .create_proof_from_account(setup.a.account, setup.trade.a_key)
+        .call_method(setup.trade.component, method, manifest_args!(counter_id));
+
+    call_as(&mut setup.test_runner, &setup.a, &mut builder)
+}
+
+fn as_b_with_key(setup: &mut Setup, method: &str) -> TransactionReceipt {
+    let mut builder = ManifestBuilder::new();
+    builder
+        .create_proof_from_account(setup.b.account, setup.trade.b_key)
+        .pop_from_auth_zone(|builder, proof| {
+            builder.call_method(setup.trade.component, method, manifest_args!(proof))
+        });
+
+    call_as(&mut setup.test_runner, &setup.b, &mut builder)
+}
+
+fn revision(setup: &mut Setup) -> u64 {
+    let manifest = ManifestBuilder::new()
+        .call_method(setup.trade.component, "revision", manifest_args!())
+        .build();
+
+    let receipt = setup
+        .test_runner
+        .execute_manifest_ignoring_fee(manifest, setup.a.signer());
+    receipt.expect_commit_success().output(0)
+}
+
+fn xrd_held(test_runner: &mut TestRunner, account: ComponentAddress) -> Decimal {
+    test_runner.get_component_resources(account)[&RADIX_TOKEN]
+}
+
+#[test]
+fn accepted_counteroffer_is_claimed_with_its_key() {
+    let mut setup = setup();
+    let b_account = setup.b.account;
+
+    counter_offer(&mut setup, dec!("5"), b_account, 0).expect_commit_success();
+    as_a(&mut setup, "accept_counter", 0).expect_commit_success();
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Filled
+    );
+
+    as_b_with_key(&mut setup, "b_claims_a_assets").expect_commit_success();
+
+    let held = setup.test_runner.get_component_resources(setup.b.account);
+    assert_eq!(held[&setup.offered], dec!("100"));
+
+    // A is paid what the counteroffer gave, not the original ask
+
+    let a_before = xrd_held(&mut setup.test_runner, setup.a.account);
+    let mut builder = ManifestBuilder::new();
+    builder
+        .create_proof_from_account(setup.a.account, setup.trade.a_key)
+        .call_method(setup.trade.component, "claim_my_new_assets", manifest_args!());
+    call_as(&mut setup.test_runner, &setup.a, &mut builder).expect_commit_success();
+
+    assert_eq!(
+        xrd_held(&mut setup.test_runner, setup.a.account),
+        a_before + dec!("5")
+    );
+}
+
+#[test]
+fn rejected_counteroffer_is_refunded_to_its_refund_account() {
+    let mut setup = setup();
+    let c = Party::new(&mut setup.test_runner);
+    let c_before = xrd_held(&mut setup.test_runner, c.account);
+
+    counter_offer(&mut setup, dec!("5"), c.account, 0).expect_commit_success();
+    as_a(&mut setup, "reject_counter", 0).expect_commit_success();
+
+    assert_eq!(
+        xrd_held(&mut setup.test_runner, c.account),
+        c_before + dec!("5")
+    );
+
+    // a rejected counteroffer can't be accepted afterwards
+
+    as_a(&mut setup, "accept_counter", 0).expect_commit_failure();
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Open
+    );
+}
+
+#[test]
+fn withdrawing_after_accept_restores_the_ask_under_a_new_revision() {
+    let mut setup = setup();
+    let b_account = setup.b.account;
+
+    counter_offer(&mut setup, dec!("5"), b_account, 0).expect_commit_success();
+    as_a(&mut setup, "accept_counter", 0).expect_commit_success();
+    let accepted_revision = revision(&mut setup);
+    assert_eq!(accepted_revision, 1);
+
+    as_b_with_key(&mut setup, "b_withdraw_deposit").expect_commit_success();
+
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Open
+    );
+    let restored_revision = revision(&mut setup);
+    assert_eq!(restored_revision, 2);
+
+    // the original ask of 10 XRD is back, and only the new revision can fill it
+
+    let c = Party::new(&mut setup.test_runner);
+    deposit_tokens(
+        &mut setup.test_runner,
+        &setup.trade,
+        &c,
+        (RADIX_TOKEN, dec!("5")),
+        restored_revision,
+    )
+    .expect_commit_failure();
+    deposit_tokens(
+        &mut setup.test_runner,
+        &setup.trade,
+        &c,
+        (RADIX_TOKEN, dec!("10")),
+        accepted_revision,
+    )
+    .expect_commit_failure();
+    deposit_tokens(
+        &mut setup.test_runner,
+        &setup.trade,
+        &c,
+        (RADIX_TOKEN, dec!("10")),
+        restored_revision,
+    )
+    .expect_commit_success();
+}
+
+#[test]
+fn pending_counteroffers_are_capped() {
+    let mut setup = setup();
+    let b_account = setup.b.account;
+
+    for _ in 0..20 {
+        counter_offer(&mut setup, dec!("1"), b_account, 0).expect_commit_success();
+    }
+    counter_offer(&mut setup, dec!("1"), b_account, 0).expect_commit_failure();
+
+    // rejecting one frees its place
+
+    as_a(&mut setup, "reject_counter", 0).expect_commit_success();
+    counter_offer(&mut setup, dec!("1"), b_account, 0).expect_commit_success();
+}
@@ -0,0 +1,234 @@
+mod common;
+
+use common::*;
+use radix_engine::transaction::TransactionReceipt;
+use scrypto::prelude::*;
+use scrypto_unit::*;
+use transaction::builder::ManifestBuilder;
+
+// B's deposits in two parts, and what happens to them when B withdraws or A cancels before B is paid
+
+struct Setup {
+    test_runner: TestRunner,
+    protocol: Protocol,
+    a: Party,
+    b: Party,
+    trade: Trade,
+    nft: NonFungibleGlobalId,
+    ask: (ResourceAddress, Decimal),
+}
+
+// A offers 100 of a fresh token for one of B's NFTs and 10 XRD
+
+fn setup() -> Setup {
+    let mut test_runner = TestRunner::builder().build();
+    let a = Party::new(&mut test_runner);
+    let b = Party::new(&mut test_runner);
+    let protocol = Protocol::without_fee(&mut test_runner, &a);
+
+    let offered = test_runner.create_fungible_resource(dec!("100"), 18, a.account);
+    let collection = test_runner.create_non_fungible_resource(b.account);
+    let nft = NonFungibleGlobalId::new(collection, NonFungibleLocalId::integer(1));
+    let ask = (RADIX_TOKEN, dec!("10"));
+
+    let mut builder = ManifestBuilder::new();
+    builder
+        .withdraw_from_account(a.account, offered, dec!("100"))
+        .take_from_worktop(offered, |builder, bucket| {
+            builder.call_function(
+                protocol.package,
+                "Barter",
+                "lets_barter_partner_permitted_clock",
+                manifest_args!(
+                    "test trade".to_string(),
+                    Vec::<ManifestBucket>::new(),
+                    vec![bucket],
+                    vec![nft.clone()],
+                    BTreeMap::from([ask]),
+                    TradeOptions::with_expiry(None),
+                    protocol.hub,
+                    Option::<ManifestProof>::None
+                ),
+            )
+        });
+    let receipt = call_as(&mut test_runner, &a, &mut builder);
+    let trade = trade_from_receipt(&mut test_runner, &receipt, &a);
+
+    Setup {
+        test_runner,
+        protocol,
+        a,
+        b,
+        trade,
+        nft,
+        ask,
+    }
+}
+
+// B's second deposit, made with a proof of the key their token deposit minted
+
+fn deposit_nft(setup: &mut Setup, refund_account: ComponentAddress) -> TransactionReceipt {
+    let mut builder = ManifestBuilder::new();
+    builder
+        .withdraw_non_fungibles_from_account(
+            setup.b.account,
+            setup.nft.resource_address(),
+            &BTreeSet::from([setup.nft.local_id().clone()]),
+        )
+        .create_proof_from_account(setup.b.account, setup.trade.b_key)
+        .pop_from_auth_zone(|builder, proof| {
+            builder.take_from_worktop(setup.nft.resource_address(), |builder, bucket| {
+                builder.call_method(
+                    setup.trade.component,
+                    "b_deposit_nfts",
+                    manifest_args!(vec![bucket], Some(proof), refund_account, 0u64),
+                )
+            })
+        });
+
+    call_as(&mut setup.test_runner, &setup.b, &mut builder)
+}
+
+fn cancel_as_a(setup: &mut Setup) {
+    cancel(&mut setup.test_runner, &setup.trade, &setup.a, false).expect_commit_success();
+}
+
+fn b_xrd(setup: &mut Setup) -> Decimal {
+    let account = setup.b.account;
+    setup.test_runner.get_component_resources(account)[&RADIX_TOKEN]
+}
+
+#[test]
+fn cancel_returns_a_partial_deposit_to_b() {
+    let mut setup = setup();
+    let before = b_xrd(&mut setup);
+
+    deposit_tokens(
+        &mut setup.test_runner,
+        &setup.trade,
+        &setup.b,
+        setup.ask,
+        0,
+    )
+    .expect_commit_success();
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Open
+    );
+
+    cancel_as_a(&mut setup);
+
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Cancelled
+    );
+    assert_eq!(b_xrd(&mut setup), before);
+
+    // nothing is left for B to withdraw
+
+    let mut builder = ManifestBuilder::new();
+    builder
+        .create_proof_from_account(setup.b.account, setup.trade.b_key)
+        .pop_from_auth_zone(|builder, proof| {
+            builder.call_method(
+                setup.trade.component,
+                "b_withdraw_deposit",
+                manifest_args!(proof),
+            )
+        });
+    call_as(&mut setup.test_runner, &setup.b, &mut builder).expect_commit_failure();
+}
+
+#[test]
+fn cancel_returns_a_complete_deposit_to_b() {
+    let mut setup = setup();
+    let before = b_xrd(&mut setup);
+
+    deposit_tokens(
+        &mut setup.test_runner,
+        &setup.trade,
+        &setup.b,
+        setup.ask,
+        0,
+    )
+    .expect_commit_success();
+    let b_account = setup.b.account;
+    deposit_nft(&mut setup, b_account).expect_commit_success();
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Filled
+    );
+
+    cancel_as_a(&mut setup);
+
+    let held = setup.test_runner.get_component_resources(setup.b.account);
+    assert_eq!(held[&RADIX_TOKEN], before);
+    assert_eq!(held[&setup.nft.resource_address()], dec!("3"));
+}
+
+#[test]
+fn withdrawn_deposit_reopens_the_trade_to_another_counterparty() {
+    let mut setup = setup();
+    let c = Party::new(&mut setup.test_runner);
+
+    deposit_tokens(
+        &mut setup.test_runner,
+        &setup.trade,
+        &setup.b,
+        setup.ask,
+        0,
+    )
+    .expect_commit_success();
+
+    // a second counterparty can't start depositing while B is part way through
+
+    deposit_tokens(&mut setup.test_runner, &setup.trade, &c, setup.ask, 0).expect_commit_failure();
+
+    let mut builder = ManifestBuilder::new();
+    builder
+        .create_proof_from_account(setup.b.account, setup.trade.b_key)
+        .pop_from_auth_zone(|builder, proof| {
+            builder.call_method(
+                setup.trade.component,
+                "b_withdraw_deposit",
+                manifest_args!(proof),
+            )
+        });
+    call_as(&mut setup.test_runner, &setup.b, &mut builder).expect_commit_success();
+
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Open
+    );
+    deposit_tokens(&mut setup.test_runner, &setup.trade, &c, setup.ask, 0).expect_commit_success();
+
+    // B's key no longer belongs to the trade's counterparty
+
+    let b_account = setup.b.account;
+    deposit_nft(&mut setup, b_account).expect_commit_failure();
+}
+
+#[test]
+fn deposit_refunding_to_anything_but_an_account_is_rejected() {
+    let mut setup = setup();
+    let not_an_account = setup.protocol.hub;
+
+    let mut builder = ManifestBuilder::new();
+    builder
+        .withdraw_from_account(setup.b.account, setup.ask.0, setup.ask.1)
+        .take_from_worktop(setup.ask.0, |builder, bucket| {
+            builder.call_method(
+                setup.trade.component,
+                "b_deposit_tokens",
+                manifest_args!(
+                    vec![bucket],
+                    Option::<ManifestProof>::None,
+                    not_an_account,
+                    0u64
+                ),
+            )
+        });
+    let receipt = call_as(&mut setup.test_runner, &setup.b, &mut builder);
+
+    receipt.expect_commit_failure();
+}
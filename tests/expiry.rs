@@ -0,0 +1,289 @@
+mod common;
+
+use common::*;
+use scrypto::prelude::*;
+use scrypto_unit::*;
+use transaction::builder::ManifestBuilder;
+
+// Expiry behaviour, checked by moving the test runner's epoch or clock past a trade's expiry. Once it has passed
+// B can no longer deposit, accept or settle, while A can still cancel and the trade closes as Expired.
+
+const EXPIRY_EPOCH: u64 = 10;
+
+// The clock is read to the minute, so the times used here are whole minutes in milliseconds
+
+const START_MS: i64 = 1_680_000_000_000;
+const EXPIRY_SECONDS: i64 = START_MS / 1000 + 3600;
+
+struct Setup {
+    test_runner: TestRunner,
+    a: Party,
+    b: Party,
+    trade: Trade,
+    ask: (ResourceAddress, Decimal),
+}
+
+// A offers 100 of a fresh token for 10 XRD, expiring at EXPIRY_EPOCH
+
+fn setup() -> Setup {
+    setup_expiring(Expiry::AtEpoch(EXPIRY_EPOCH))
+}
+
+fn setup_expiring(expiry: Expiry) -> Setup {
+    let mut test_runner = TestRunner::builder().build();
+    test_runner.set_current_epoch(1);
+    test_runner.set_current_time(START_MS);
+
+    let a = Party::new(&mut test_runner);
+    let protocol = Protocol::without_fee(&mut test_runner, &a);
+    let b = Party::new(&mut test_runner);
+
+    let offered = test_runner.create_fungible_resource(dec!("100"), 18, a.account);
+    let ask = (RADIX_TOKEN, dec!("10"));
+
+    let receipt = propose(
+        &mut test_runner,
+        &protocol,
+        &a,
+        (offered, dec!("100")),
+        BTreeMap::from([ask]),
+        TradeOptions::with_expiry(Some(expiry)),
+    );
+    let trade = trade_from_receipt(&mut test_runner, &receipt, &a);
+
+    Setup {
+        test_runner,
+        a,
+        b,
+        trade,
+        ask,
+    }
+}
+
+#[test]
+fn proposal_with_past_expiry_is_rejected() {
+    let mut test_runner = TestRunner::builder().build();
+    test_runner.set_current_epoch(EXPIRY_EPOCH);
+
+    let a = Party::new(&mut test_runner);
+    let protocol = Protocol::without_fee(&mut test_runner, &a);
+    let offered = test_runner.create_fungible_resource(dec!("100"), 18, a.account);
+
+    let receipt = propose(
+        &mut test_runner,
+        &protocol,
+        &a,
+        (offered, dec!("100")),
+        BTreeMap::from([(RADIX_TOKEN, dec!("10"))]),
+        TradeOptions::with_expiry(Some(Expiry::AtEpoch(EXPIRY_EPOCH))),
+    );
+
+    receipt.expect_commit_failure();
+}
+
+#[test]
+fn deposit_before_expiry_is_accepted() {
+    let mut setup = setup();
+
+    let receipt = deposit_tokens(
+        &mut setup.test_runner,
+        &setup.trade,
+        &setup.b,
+        setup.ask,
+        0,
+    );
+
+    receipt.expect_commit_success();
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Filled
+    );
+}
+
+#[test]
+fn deposit_after_expiry_is_rejected() {
+    let mut setup = setup();
+    setup.test_runner.set_current_epoch(EXPIRY_EPOCH);
+
+    let receipt = deposit_tokens(
+        &mut setup.test_runner,
+        &setup.trade,
+        &setup.b,
+        setup.ask,
+        0,
+    );
+
+    receipt.expect_commit_failure();
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Open
+    );
+}
+
+#[test]
+fn accept_after_expiry_is_rejected() {
+    let mut setup = setup();
+    setup.test_runner.set_current_epoch(EXPIRY_EPOCH + 1);
+
+    let mut builder = ManifestBuilder::new();
+    builder
+        .withdraw_from_account(setup.b.account, setup.ask.0, setup.ask.1)
+        .take_from_worktop(setup.ask.0, |builder, bucket| {
+            builder.call_method(
+                setup.trade.component,
+                "accept",
+                manifest_args!(vec![bucket], 0u64),
+            )
+        });
+    let receipt = call_as(&mut setup.test_runner, &setup.b, &mut builder);
+
+    receipt.expect_commit_failure();
+}
+
+#[test]
+fn claim_after_expiry_is_rejected() {
+    let mut setup = setup();
+
+    deposit_tokens(
+        &mut setup.test_runner,
+        &setup.trade,
+        &setup.b,
+        setup.ask,
+        0,
+    )
+    .expect_commit_success();
+
+    setup.test_runner.set_current_epoch(EXPIRY_EPOCH);
+
+    let mut builder = ManifestBuilder::new();
+    builder
+        .create_proof_from_account(setup.b.account, setup.trade.b_key)
+        .pop_from_auth_zone(|builder, proof| {
+            builder.call_method(
+                setup.trade.component,
+                "b_claims_a_assets",
+                manifest_args!(proof),
+            )
+        });
+    let receipt = call_as(&mut setup.test_runner, &setup.b, &mut builder);
+
+    receipt.expect_commit_failure();
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Filled
+    );
+}
+
+#[test]
+fn cancel_after_expiry_closes_the_trade_as_expired() {
+    let mut setup = setup();
+    let b_balance = setup.test_runner.get_component_resources(setup.b.account)[&setup.ask.0];
+
+    deposit_tokens(
+        &mut setup.test_runner,
+        &setup.trade,
+        &setup.b,
+        setup.ask,
+        0,
+    )
+    .expect_commit_success();
+
+    setup.test_runner.set_current_epoch(EXPIRY_EPOCH);
+
+    let mut builder = ManifestBuilder::new();
+    builder
+        .create_proof_from_account(setup.a.account, setup.trade.a_key)
+        .call_method(setup.trade.component, "cancel", manifest_args!());
+    let receipt = call_as(&mut setup.test_runner, &setup.a, &mut builder);
+
+    receipt.expect_commit_success();
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Expired
+    );
+
+    // the deposit the expired trade never paid out is back with B
+
+    let held = setup.test_runner.get_component_resources(setup.b.account);
+    assert_eq!(held[&setup.ask.0], b_balance);
+}
+
+#[test]
+fn cancel_before_expiry_closes_the_trade_as_cancelled() {
+    let mut setup = setup();
+
+    let mut builder = ManifestBuilder::new();
+    builder
+        .create_proof_from_account(setup.a.account, setup.trade.a_key)
+        .call_method(setup.trade.component, "cancel", manifest_args!());
+    let receipt = call_as(&mut setup.test_runner, &setup.a, &mut builder);
+
+    receipt.expect_commit_success();
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Cancelled
+    );
+}
+
+#[test]
+fn deposit_before_expiry_time_is_accepted() {
+    let mut setup = setup_expiring(Expiry::AtTime(Instant::new(EXPIRY_SECONDS)));
+    setup
+        .test_runner
+        .set_current_time((EXPIRY_SECONDS - 60) * 1000);
+
+    let receipt = deposit_tokens(
+        &mut setup.test_runner,
+        &setup.trade,
+        &setup.b,
+        setup.ask,
+        0,
+    );
+
+    receipt.expect_commit_success();
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Filled
+    );
+}
+
+#[test]
+fn deposit_after_expiry_time_is_rejected() {
+    let mut setup = setup_expiring(Expiry::AtTime(Instant::new(EXPIRY_SECONDS)));
+    setup.test_runner.set_current_time(EXPIRY_SECONDS * 1000);
+
+    let receipt = deposit_tokens(
+        &mut setup.test_runner,
+        &setup.trade,
+        &setup.b,
+        setup.ask,
+        0,
+    );
+
+    receipt.expect_commit_failure();
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Open
+    );
+}
+
+#[test]
+fn proposal_with_past_expiry_time_is_rejected() {
+    let mut test_runner = TestRunner::builder().build();
+    test_runner.set_current_time(EXPIRY_SECONDS * 1000);
+
+    let a = Party::new(&mut test_runner);
+    let protocol = Protocol::without_fee(&mut test_runner, &a);
+    let offered = test_runner.create_fungible_resource(dec!("100"), 18, a.account);
+
+    let receipt = propose(
+        &mut test_runner,
+        &protocol,
+        &a,
+        (offered, dec!("100")),
+        BTreeMap::from([(RADIX_TOKEN, dec!("10"))]),
+        TradeOptions::with_expiry(Some(Expiry::AtTime(Instant::new(EXPIRY_SECONDS)))),
+    );
+
+    receipt.expect_commit_failure();
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use common::*;
+use radix_engine::transaction::TransactionReceipt;
+use scrypto::prelude::*;
+use scrypto_unit::*;
+use transaction::builder::ManifestBuilder;
+
+// The hub lists every trade under the creator badge it was proposed with, so one user's trades can be found
+// together even though each has its own A key.
+
+#[derive(ScryptoSbor, Clone, Debug)]
+struct TradeListing {
+    component: ComponentAddress,
+    creator: Option<NonFungibleGlobalId>,
+    offered: Vec<ResourceAddress>,
+    wanted: Vec<ResourceAddress>,
+    status: TradeStatus,
+    short_code: String,
+}
+
+fn hub_call<T: ScryptoDecode>(
+    test_runner: &mut TestRunner,
+    protocol: &Protocol,
+    party: &Party,
+    method: &str,
+    args: ManifestValue,
+) -> T {
+    let mut builder = ManifestBuilder::new();
+    builder.call_method(protocol.hub, method, args);
+    let receipt = call_as(test_runner, party, &mut builder);
+    receipt.expect_commit_success().output(0)
+}
+
+// A offers 10 of `offered` for 1 XRD, presenting a proof of `badge` as the creator
+
+fn propose_as_creator(
+    test_runner: &mut TestRunner,
+    protocol: &Protocol,
+    a: &Party,
+    badge: ResourceAddress,
+    offered: ResourceAddress,
+) -> TransactionReceipt {
+    let mut builder = ManifestBuilder::new();
+    builder
+        .create_proof_from_account(a.account, badge)
+        .pop_from_auth_zone(|builder, proof| {
+            builder
+                .withdraw_from_account(a.account, offered, dec!("10"))
+                .take_from_worktop(offered, |builder, bucket| {
+                    builder.call_function(
+                        protocol.package,
+                        "Barter",
+                        "lets_barter_partner_permitted_clock",
+                        manifest_args!(
+                            "test trade".to_string(),
+                            Vec::<ManifestBucket>::new(),
+                            vec![bucket],
+                            Vec::<NonFungibleGlobalId>::new(),
+                            BTreeMap::from([(RADIX_TOKEN, dec!("1"))]),
+                            TradeOptions::with_expiry(None),
+                            protocol.hub,
+                            Some(proof)
+                        ),
+                    )
+                })
+        });
+
+    call_as(test_runner, a, &mut builder)
+}
+
+#[test]
+fn trades_are_found_by_their_creator_badge() {
+    let mut test_runner = TestRunner::builder().build();
+    let a = Party::new(&mut test_runner);
+    let b = Party::new(&mut test_runner);
+    let protocol = Protocol::without_fee(&mut test_runner, &a);
+
+    let creator_badge: ResourceAddress =
+        hub_call(&mut test_runner, &protocol, &a, "creator_badge", manifest_args!());
+
+    let mut builder = ManifestBuilder::new();
+    builder.call_method(protocol.hub, "register_creator", manifest_args!());
+    call_as(&mut test_runner, &a, &mut builder).expect_commit_success();
+
+    let offered = test_runner.create_fungible_resource(dec!("100"), 18, a.account);
+
+    let mut created: Vec<ComponentAddress> = Vec::new();
+    for _ in 0..2 {
+        let receipt = propose_as_creator(&mut test_runner, &protocol, &a, creator_badge, offered);
+        created.push(trade_from_receipt(&mut test_runner, &receipt, &a).component);
+    }
+
+    // another user's trade, proposed without a badge
+
+    let other = test_runner.create_fungible_resource(dec!("100"), 18, b.account);
+    propose(
+        &mut test_runner,
+        &protocol,
+        &b,
+        (other, dec!("100")),
+        BTreeMap::from([(RADIX_TOKEN, dec!("1"))]),
+        TradeOptions::with_expiry(None),
+    )
+    .expect_commit_success();
+
+    let listing: Option<TradeListing> = hub_call(
+        &mut test_runner,
+        &protocol,
+        &a,
+        "trade",
+        manifest_args!(created[0]),
+    );
+    let creator = listing
+        .unwrap()
+        .creator
+        .expect("Trade was not listed under its creator badge");
+    assert_eq!(creator.resource_address(), creator_badge);
+
+    let (found, next): (Vec<TradeListing>, Option<u64>) = hub_call(
+        &mut test_runner,
+        &protocol,
+        &a,
+        "find_trades",
+        manifest_args!(
+            Option::<ResourceAddress>::None,
+            Option::<ResourceAddress>::None,
+            Some(creator),
+            Option::<TradeStatus>::None,
+            0u64,
+            10u32
+        ),
+    );
+
+    let found: Vec<ComponentAddress> = found.iter().map(|listing| listing.component).collect();
+    assert_eq!(found, created);
+    assert_eq!(next, None);
+}
+
+#[test]
+fn proof_of_anything_but_a_creator_badge_is_rejected() {
+    let mut test_runner = TestRunner::builder().build();
+    let a = Party::new(&mut test_runner);
+    let protocol = Protocol::without_fee(&mut test_runner, &a);
+
+    let offered = test_runner.create_fungible_resource(dec!("100"), 18, a.account);
+    let not_a_badge = test_runner.create_fungible_resource(dec!("1"), 0, a.account);
+
+    let receipt = propose_as_creator(&mut test_runner, &protocol, &a, not_a_badge, offered);
+
+    receipt.expect_commit_failure();
+}
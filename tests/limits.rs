@@ -0,0 +1,133 @@
+mod common;
+
+use common::*;
+use scrypto::prelude::*;
+use scrypto_unit::*;
+
+// TradeLimits::CEILING is only safe if a side filled right up to it can still be emptied in one transaction.
+// These run the cancel, and the claim that settles the trade, under the normal cost unit limit rather than
+// ignoring the fee.
+
+#[test]
+fn trade_at_the_ceiling_can_be_cancelled_in_one_transaction() {
+    let mut test_runner = TestRunner::builder().build();
+    let a = Party::new(&mut test_runner);
+    let protocol = Protocol::without_fee(&mut test_runner, &a);
+
+    // 10 fungibles and 10 collections of 5 - 20 resources and 50 NFTs, every ceiling at once
+
+    let (tokens, collections) = bench_assets(&mut test_runner, &protocol, &a, 10, 10, 5, None);
+
+    let receipt = propose_assets(
+        &mut test_runner,
+        &protocol,
+        &a,
+        &collections,
+        &tokens,
+        BTreeMap::from([(RADIX_TOKEN, dec!("1"))]),
+        TradeOptions::with_expiry(None),
+    );
+    let trade = trade_from_receipt(&mut test_runner, &receipt, &a);
+
+    cancel(&mut test_runner, &trade, &a, true).expect_commit_success();
+
+    let held = test_runner.get_component_resources(a.account);
+    for resource_address in tokens.iter() {
+        assert_eq!(held[resource_address], dec!("1000"));
+    }
+    for resource_address in collections.iter() {
+        assert_eq!(held[resource_address], dec!("5"));
+    }
+}
+
+// Settlement also pays the protocol fee and a royalty for every collection before A's side is emptied, so it
+// is the most expensive way out of a trade
+
+#[test]
+fn trade_at_the_ceiling_can_be_settled_with_fees_and_royalties_in_one_transaction() {
+    let mut test_runner = TestRunner::builder().build();
+    let a = Party::new(&mut test_runner);
+    let b = Party::new(&mut test_runner);
+    let creator = Party::new(&mut test_runner);
+    let protocol = Protocol::new(&mut test_runner, &a, ProtocolFee::Percentage(dec!("1")));
+
+    // every collection takes 5% of the price, each paid out separately
+
+    let (tokens, collections) = bench_assets(
+        &mut test_runner,
+        &protocol,
+        &a,
+        10,
+        10,
+        5,
+        Some((dec!("5"), creator.account)),
+    );
+
+    let receipt = propose_assets(
+        &mut test_runner,
+        &protocol,
+        &a,
+        &collections,
+        &tokens,
+        BTreeMap::from([(RADIX_TOKEN, dec!("100"))]),
+        TradeOptions::with_expiry(None),
+    );
+    let trade = trade_from_receipt(&mut test_runner, &receipt, &a);
+
+    // the 100 XRD ask plus the 1% fee
+
+    deposit_tokens(&mut test_runner, &trade, &b, (RADIX_TOKEN, dec!("101")), 0)
+        .expect_commit_success();
+
+    let creator_before = test_runner.get_component_resources(creator.account)[&RADIX_TOKEN];
+
+    let manifest = ManifestBuilder::new()
+        .lock_fee(b.account, dec!("100"))
+        .create_proof_from_account(b.account, trade.b_key)
+        .pop_from_auth_zone(|builder, proof| {
+            builder.call_method(trade.component, "b_claims_a_assets", manifest_args!(proof))
+        })
+        .call_method(
+            b.account,
+            "deposit_batch",
+            manifest_args!(ManifestExpression::EntireWorktop),
+        )
+        .build();
+    test_runner
+        .execute_manifest(manifest, b.signer())
+        .expect_commit_success();
+
+    let held = test_runner.get_component_resources(b.account);
+    for resource_address in tokens.iter() {
+        assert_eq!(held[resource_address], dec!("1000"));
+    }
+    for resource_address in collections.iter() {
+        assert_eq!(held[resource_address], dec!("5"));
+    }
+
+    assert_eq!(
+        test_runner.get_component_resources(creator.account)[&RADIX_TOKEN],
+        creator_before + dec!("5")
+    );
+}
+
+#[test]
+fn trade_above_the_ceiling_is_rejected() {
+    let mut test_runner = TestRunner::builder().build();
+    let a = Party::new(&mut test_runner);
+    let protocol = Protocol::without_fee(&mut test_runner, &a);
+
+    let (tokens, _) = bench_assets(&mut test_runner, &protocol, &a, 11, 0, 0, None);
+
+    let receipt = propose_assets(
+        &mut test_runner,
+        &protocol,
+        &a,
+        &[],
+        &tokens,
+        BTreeMap::from([(RADIX_TOKEN, dec!("1"))]),
+        TradeOptions::with_expiry(None),
+    );
+
+    receipt.expect_commit_failure();
+}
@@ -0,0 +1,231 @@
+mod common;
+
+use common::*;
+use radix_engine::transaction::TransactionReceipt;
+use scrypto::prelude::*;
+use scrypto_unit::*;
+use transaction::builder::ManifestBuilder;
+
+// A three-way swap of tokens. The creator keeps every key, so one account acts for all three participants and
+// names which one each call is for.
+
+#[derive(ManifestSbor)]
+struct SwapLeg {
+    nfts: Vec<NonFungibleGlobalId>,
+    tokens: BTreeMap<ResourceAddress, Decimal>,
+}
+
+struct Setup {
+    test_runner: TestRunner,
+    a: Party,
+    swap: ComponentAddress,
+    key_resource: ResourceAddress,
+    tokens: Vec<ResourceAddress>,
+}
+
+// participant i gives 10 of token i
+
+fn setup() -> Setup {
+    let mut test_runner = TestRunner::builder().build();
+    let package = test_runner.compile_and_publish(this_package!());
+    let a = Party::new(&mut test_runner);
+
+    let tokens: Vec<ResourceAddress> = (0..3)
+        .map(|_| test_runner.create_fungible_resource(dec!("100"), 18, a.account))
+        .collect();
+
+    let legs: Vec<SwapLeg> = tokens
+        .iter()
+        .map(|token| SwapLeg {
+            nfts: Vec::new(),
+            tokens: BTreeMap::from([(*token, dec!("10"))]),
+        })
+        .collect();
+
+    let mut builder = ManifestBuilder::new();
+    builder.call_function(
+        package,
+        "MultiSwap",
+        "instantiate_multi_swap",
+        manifest_args!("test swap".to_string(), legs, Option::<Expiry>::None),
+    );
+    let receipt = call_as(&mut test_runner, &a, &mut builder);
+
+    let commit = receipt.expect_commit_success();
+    let swap = commit.new_component_addresses()[0];
+    let held = test_runner.get_component_resources(a.account);
+    let key_resource = *commit
+        .new_resource_addresses()
+        .iter()
+        .find(|resource_address| held.get(*resource_address) == Some(&dec!("3")))
+        .expect("The swap keys were not returned");
+
+    Setup {
+        test_runner,
+        a,
+        swap,
+        key_resource,
+        tokens,
+    }
+}
+
+fn deposit_leg(setup: &mut Setup, participant: u32) -> TransactionReceipt {
+    let token = setup.tokens[participant as usize];
+
+    let mut builder = ManifestBuilder::new();
+    builder
+        .withdraw_from_account(setup.a.account, token, dec!("10"))
+        .create_proof_from_account(setup.a.account, setup.key_resource)
+        .pop_from_auth_zone(|builder, proof| {
+            builder.take_from_worktop(token, |builder, bucket| {
+                builder.call_method(
+                    setup.swap,
+                    "deposit_leg",
+                    manifest_args!(proof, participant, vec![bucket]),
+                )
+            })
+        });
+
+    call_as(&mut setup.test_runner, &setup.a, &mut builder)
+}
+
+// withdraw_leg and claim_leg, called by `party` with a proof of every swap key they hold
+
+fn keyed_call(
+    test_runner: &mut TestRunner,
+    setup_swap: (ComponentAddress, ResourceAddress),
+    party: &Party,
+    method: &str,
+    participant: u32,
+) -> TransactionReceipt {
+    let (swap, key_resource) = setup_swap;
+
+    let mut builder = ManifestBuilder::new();
+    builder
+        .create_proof_from_account(party.account, key_resource)
+        .pop_from_auth_zone(|builder, proof| {
+            builder.call_method(swap, method, manifest_args!(proof, participant))
+        });
+
+    call_as(test_runner, party, &mut builder)
+}
+
+fn as_a(setup: &mut Setup, method: &str, participant: u32) -> TransactionReceipt {
+    keyed_call(
+        &mut setup.test_runner,
+        (setup.swap, setup.key_resource),
+        &setup.a,
+        method,
+        participant,
+    )
+}
+
+fn read<T: ScryptoDecode>(setup: &mut Setup, method: &str) -> T {
+    let manifest = ManifestBuilder::new()
+        .call_method(setup.swap, method, manifest_args!())
+        .build();
+
+    let receipt = setup
+        .test_runner
+        .execute_manifest_ignoring_fee(manifest, setup.a.signer());
+    receipt.expect_commit_success().output(0)
+}
+
+fn held(setup: &mut Setup, resource_address: ResourceAddress) -> Decimal {
+    let account = setup.a.account;
+    setup.test_runner.get_component_resources(account)[&resource_address]
+}
+
+#[test]
+fn deposited_leg_can_be_withdrawn_before_the_swap_settles() {
+    let mut setup = setup();
+    let token = setup.tokens[0];
+
+    deposit_leg(&mut setup, 0).expect_commit_success();
+    assert_eq!(read::<Vec<bool>>(&mut setup, "funded"), vec![true, false, false]);
+    assert_eq!(held(&mut setup, token), dec!("90"));
+
+    // the same leg can't be deposited twice
+
+    deposit_leg(&mut setup, 0).expect_commit_failure();
+
+    as_a(&mut setup, "withdraw_leg", 0).expect_commit_success();
+    assert_eq!(read::<Vec<bool>>(&mut setup, "funded"), vec![false, false, false]);
+    assert_eq!(held(&mut setup, token), dec!("100"));
+
+    as_a(&mut setup, "withdraw_leg", 0).expect_commit_failure();
+}
+
+#[test]
+fn funding_every_leg_settles_and_each_participant_claims_the_next_leg() {
+    let mut setup = setup();
+
+    for participant in 0..3 {
+        deposit_leg(&mut setup, participant).expect_commit_success();
+    }
+    assert_eq!(read::<TradeStatus>(&mut setup, "status"), TradeStatus::Settled);
+
+    // legs can't be withdrawn once the swap has settled
+
+    as_a(&mut setup, "withdraw_leg", 0).expect_commit_failure();
+
+    // participant 0 receives leg 1, participant 1 leg 2 and participant 2 leg 0
+
+    for participant in 0..3u32 {
+        let received = setup.tokens[((participant + 1) % 3) as usize];
+        let before = held(&mut setup, received);
+
+        as_a(&mut setup, "claim_leg", participant).expect_commit_success();
+        assert_eq!(held(&mut setup, received), before + dec!("10"));
+    }
+
+    as_a(&mut setup, "claim_leg", 0).expect_commit_failure();
+}
+
+#[test]
+fn a_key_only_acts_for_its_own_participant() {
+    let mut setup = setup();
+    let b = Party::new(&mut setup.test_runner);
+
+    for participant in 0..3 {
+        deposit_leg(&mut setup, participant).expect_commit_success();
+    }
+
+    // B is handed one of the three keys
+
+    let mut builder = ManifestBuilder::new();
+    builder
+        .withdraw_from_account(setup.a.account, setup.key_resource, dec!("1"))
+        .call_method(
+            b.account,
+            "deposit_batch",
+            manifest_args!(ManifestExpression::EntireWorktop),
+        );
+    call_as(&mut setup.test_runner, &setup.a, &mut builder).expect_commit_success();
+
+    let mut claimed_by_b: Vec<u32> = Vec::new();
+    for participant in 0..3 {
+        let receipt = keyed_call(
+            &mut setup.test_runner,
+            (setup.swap, setup.key_resource),
+            &b,
+            "claim_leg",
+            participant,
+        );
+        if receipt.is_commit_success() {
+            claimed_by_b.push(participant);
+        }
+    }
+    assert_eq!(claimed_by_b.len(), 1);
+
+    // A still holds the other two keys, and no key covers a position past the last participant
+
+    for participant in 0..3 {
+        let receipt = as_a(&mut setup, "claim_leg", participant);
+        assert_eq!(
+            receipt.is_commit_success(),
+            !claimed_by_b.contains(&participant)
+        );
+    }
+    as_a(&mut setup, "claim_leg", 3).expect_commit_failure();
+}
@@ -0,0 +1,223 @@
+mod common;
+
+use common::*;
+use radix_engine::transaction::TransactionReceipt;
+use scrypto::prelude::*;
+use scrypto_unit::*;
+use transaction::builder::ManifestBuilder;
+
+// Fungible-for-fungible trades filled a fraction at a time with fill_partial
+
+struct Setup {
+    test_runner: TestRunner,
+    protocol: Protocol,
+    a: Party,
+    trade: Trade,
+    offered: ResourceAddress,
+}
+
+// A offers all of `offered_amount` of a fresh token with the given divisibility for `ask` XRD
+
+fn setup(
+    fee_terms: ProtocolFee,
+    divisibility: u8,
+    offered_amount: Decimal,
+    ask: Decimal,
+) -> Setup {
+    let mut test_runner = TestRunner::builder().build();
+    let a = Party::new(&mut test_runner);
+    let protocol = Protocol::new(&mut test_runner, &a, fee_terms);
+
+    let offered = test_runner.create_fungible_resource(offered_amount, divisibility, a.account);
+
+    let receipt = propose(
+        &mut test_runner,
+        &protocol,
+        &a,
+        (offered, offered_amount),
+        BTreeMap::from([(RADIX_TOKEN, ask)]),
+        TradeOptions::with_expiry(None),
+    );
+    let trade = trade_from_receipt(&mut test_runner, &receipt, &a);
+
+    Setup {
+        test_runner,
+        protocol,
+        a,
+        trade,
+        offered,
+    }
+}
+
+// 90 of an 18 decimal token for 30 XRD, without a fee
+
+fn setup_thirds() -> Setup {
+    setup(
+        ProtocolFee::Flat(Decimal::zero()),
+        18,
+        dec!("90"),
+        dec!("30"),
+    )
+}
+
+// the taker hands over `pay` XRD and gets the change back
+
+fn fill(setup: &mut Setup, taker: &Party, pay: Decimal, fraction: Decimal) -> TransactionReceipt {
+    let mut builder = ManifestBuilder::new();
+    builder
+        .withdraw_from_account(taker.account, RADIX_TOKEN, pay)
+        .take_from_worktop(RADIX_TOKEN, |builder, bucket| {
+            builder.call_method(
+                setup.trade.component,
+                "fill_partial",
+                manifest_args!(vec![bucket], fraction, 0u64),
+            )
+        });
+
+    call_as(&mut setup.test_runner, taker, &mut builder)
+}
+
+fn as_a(setup: &mut Setup, method: &str) -> TransactionReceipt {
+    let mut builder = ManifestBuilder::new();
+    builder
+        .create_proof_from_account(setup.a.account, setup.trade.a_key)
+        .call_method(setup.trade.component, method, manifest_args!());
+
+    call_as(&mut setup.test_runner, &setup.a, &mut builder)
+}
+
+fn held(
+    test_runner: &mut TestRunner,
+    account: ComponentAddress,
+    resource_address: ResourceAddress,
+) -> Decimal {
+    test_runner
+        .get_component_resources(account)
+        .get(&resource_address)
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[test]
+fn fills_of_a_third_settle_the_whole_trade() {
+    let mut setup = setup_thirds();
+    let third = dec!("0.333333333333333333");
+
+    let takers: Vec<Party> = (0..3).map(|_| Party::new(&mut setup.test_runner)).collect();
+
+    fill(&mut setup, &takers[0], dec!("11"), third).expect_commit_success();
+    fill(&mut setup, &takers[1], dec!("11"), third).expect_commit_success();
+
+    // the last fill takes exactly what is unfilled, and with it everything left on A's side
+
+    let remainder = Decimal::one() - third - third;
+    fill(&mut setup, &takers[2], dec!("11"), remainder).expect_commit_success();
+
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Settled
+    );
+
+    let mut received = Decimal::zero();
+    for taker in takers.iter() {
+        received += held(&mut setup.test_runner, taker.account, setup.offered);
+    }
+    assert_eq!(received, dec!("90"));
+
+    // A collects every fill's payment
+
+    let before = held(&mut setup.test_runner, setup.a.account, RADIX_TOKEN);
+    as_a(&mut setup, "claim_proceeds").expect_commit_success();
+    assert_eq!(
+        held(&mut setup.test_runner, setup.a.account, RADIX_TOKEN),
+        before + dec!("30")
+    );
+}
+
+#[test]
+fn fill_too_small_to_receive_anything_is_rejected() {
+    // 10 whole tokens for 10 XRD - a twentieth of the trade would be half a token
+
+    let mut setup = setup(
+        ProtocolFee::Flat(Decimal::zero()),
+        DIVISIBILITY_NONE,
+        dec!("10"),
+        dec!("10"),
+    );
+    let taker = Party::new(&mut setup.test_runner);
+
+    fill(&mut setup, &taker, dec!("1"), dec!("0.05")).expect_commit_failure();
+
+    fill(&mut setup, &taker, dec!("1"), dec!("0.1")).expect_commit_success();
+    assert_eq!(
+        held(&mut setup.test_runner, taker.account, setup.offered),
+        dec!("1")
+    );
+}
+
+#[test]
+fn proceeds_can_be_claimed_before_and_after_cancel() {
+    let mut setup = setup_thirds();
+    let b = Party::new(&mut setup.test_runner);
+    let c = Party::new(&mut setup.test_runner);
+
+    let start = held(&mut setup.test_runner, setup.a.account, RADIX_TOKEN);
+
+    fill(&mut setup, &b, dec!("15"), dec!("0.5")).expect_commit_success();
+    as_a(&mut setup, "claim_proceeds").expect_commit_success();
+    assert_eq!(
+        held(&mut setup.test_runner, setup.a.account, RADIX_TOKEN),
+        start + dec!("15")
+    );
+
+    fill(&mut setup, &c, dec!("7.5"), dec!("0.25")).expect_commit_success();
+
+    // cancel hands back only the quarter nobody filled
+
+    as_a(&mut setup, "cancel").expect_commit_success();
+    assert_eq!(
+        status(&mut setup.test_runner, &setup.trade, &setup.a),
+        TradeStatus::Cancelled
+    );
+    assert_eq!(
+        held(&mut setup.test_runner, setup.a.account, setup.offered),
+        dec!("22.5")
+    );
+
+    as_a(&mut setup, "claim_proceeds").expect_commit_success();
+    assert_eq!(
+        held(&mut setup.test_runner, setup.a.account, RADIX_TOKEN),
+        start + dec!("22.5")
+    );
+
+    as_a(&mut setup, "claim_proceeds").expect_commit_failure();
+}
+
+#[test]
+fn fees_are_scaled_to_the_fraction_filled() {
+    // a 1% fee on a 30 XRD ask is 0.3 XRD for the whole trade
+
+    let mut setup = setup(
+        ProtocolFee::Percentage(dec!("1")),
+        18,
+        dec!("90"),
+        dec!("30"),
+    );
+    let b = Party::new(&mut setup.test_runner);
+    let before = held(&mut setup.test_runner, b.account, RADIX_TOKEN);
+
+    fill(&mut setup, &b, dec!("20"), dec!("0.5")).expect_commit_success();
+
+    assert_eq!(
+        held(&mut setup.test_runner, b.account, RADIX_TOKEN),
+        before - dec!("15.15")
+    );
+    assert_eq!(
+        held(&mut setup.test_runner, setup.protocol.fee_collector, RADIX_TOKEN),
+        dec!("0.15")
+    );
+    assert_eq!(
+        held(&mut setup.test_runner, b.account, setup.offered),
+        dec!("45")
+    );
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use common::*;
+use scrypto::prelude::*;
+use scrypto_unit::*;
+
+// A's side is what cancel and B's claim return, so a trade that offers nothing could never be closed
+
+#[test]
+fn proposal_offering_nothing_is_rejected() {
+    let mut test_runner = TestRunner::builder().build();
+    let a = Party::new(&mut test_runner);
+    let protocol = Protocol::without_fee(&mut test_runner, &a);
+
+    let receipt = propose_assets(
+        &mut test_runner,
+        &protocol,
+        &a,
+        &[],
+        &[],
+        BTreeMap::from([(RADIX_TOKEN, dec!("10"))]),
+        TradeOptions::with_expiry(None),
+    );
+
+    receipt.expect_commit_failure();
+}
+
+// The fee collector comes from the hub, so a trade can't be pointed at anything else to pick its own fee
+
+#[test]
+fn proposal_against_something_other_than_a_hub_is_rejected() {
+    let mut test_runner = TestRunner::builder().build();
+    let a = Party::new(&mut test_runner);
+    let protocol = Protocol::without_fee(&mut test_runner, &a);
+    let offered = test_runner.create_fungible_resource(dec!("100"), 18, a.account);
+
+    let not_a_hub = Protocol {
+        hub: protocol.fee_collector,
+        ..protocol
+    };
+
+    let receipt = propose(
+        &mut test_runner,
+        &not_a_hub,
+        &a,
+        (offered, dec!("100")),
+        BTreeMap::from([(RADIX_TOKEN, dec!("10"))]),
+        TradeOptions::with_expiry(None),
+    );
+
+    receipt.expect_commit_failure();
+}
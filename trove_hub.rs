@@ -0,0 +1,355 @@
+use crate::barter_olc::TradeStatus;
+use crate::validation::assert_blueprint;
+use scrypto::prelude::*;
+
+// OVERVIEW OF BLUEPRINT
+
+// A registry for Barter components. Every Barter is created against a hub and lists itself there with what it
+// offers, what it wants, who proposed it and its current status, so the registry can be used to discover trades
+// without going through the TROVE back-end.
+
+// A's key is minted fresh for every trade, so it can't say who is behind more than one. Users who want their
+// trades found together take a creator badge from register_creator once and present a proof of it whenever
+// they propose - the trade is then listed under the badge's id, which find_trades can filter on.
+
+// A Barter registers itself from its constructor through register_trade. The hub only lists Barters of this
+// package that name it as their hub, and each only once, and hands each a registrar badge. The Barter uses it to
+// report every status change, and any amendment to what it offers or wants, back to the hub - record_status and
+// record_terms only accept calls made with that badge.
+
+// The hub names the fee collector every Barter created against it pays - see Barter's constructor.
+
+// Each listed trade also gets a short code, derived from a hash of its component address, that anyone can
+// resolve to the component through resolve_short_code. The code is made longer if its shortest form is
+// already taken, and it is released once the trade settles, is cancelled or expires.
+
+// Listings are indexed by position in two KeyValueStores - every trade ever created, and the live trades that
+// are still Open or Filled. A trade leaves the live index as soon as it settles, is cancelled or expires, by
+// moving the last live trade into its place. find_trades reads one page of an index at a time, so a search
+// never loads more listings than the limit it is given.
+
+#[blueprint]
+mod trove_hub {
+
+    struct TroveHub {
+        trades: KeyValueStore<ComponentAddress, TradeListing>,
+        trade_index: KeyValueStore<u64, ComponentAddress>,
+        trade_count: u64,
+        live_index: KeyValueStore<u64, ComponentAddress>,
+        live_positions: KeyValueStore<ComponentAddress, Option<u64>>,
+        live_count: u64,
+        short_codes: KeyValueStore<String, Option<ComponentAddress>>,
+        registrar_minter: Vault,
+        registrar_badge: ResourceAddress,
+        creator_badge: ResourceAddress,
+        fee_collector: ComponentAddress,
+    }
+
+    impl TroveHub {
+        pub fn instantiate_trove_hub(fee_collector: ComponentAddress) -> ComponentAddress {
+            assert_blueprint(fee_collector, "FeeCollector");
+
+            let registrar_minter: Bucket = ResourceBuilder::new_fungible()
+                .divisibility(DIVISIBILITY_NONE)
+                .mint_initial_supply(1);
+
+            let registrar_badge = ResourceBuilder::new_fungible()
+                .divisibility(DIVISIBILITY_NONE)
+                .metadata("name", "TROVE trade registrar")
+                .mintable(rule!(require(registrar_minter.resource_address())), LOCKED)
+                .burnable(rule!(require(registrar_minter.resource_address())), LOCKED)
+                .create_with_no_initial_supply();
+
+            let creator_badge = ResourceBuilder::new_uuid_non_fungible::<CreatorBadge>()
+                .metadata("name", "TROVE creator")
+                .mintable(rule!(require(registrar_minter.resource_address())), LOCKED)
+                .create_with_no_initial_supply();
+
+            let rules = AccessRulesConfig::new()
+                .method("record_status", rule!(require(registrar_badge)), LOCKED)
+                .method("record_terms", rule!(require(registrar_badge)), LOCKED)
+                .default(AccessRule::AllowAll, AccessRule::DenyAll);
+
+            let component = Self {
+                trades: KeyValueStore::new(),
+                trade_index: KeyValueStore::new(),
+                trade_count: 0,
+                live_index: KeyValueStore::new(),
+                live_positions: KeyValueStore::new(),
+                live_count: 0,
+                short_codes: KeyValueStore::new(),
+                registrar_minter: Vault::with_bucket(registrar_minter),
+                registrar_badge,
+                creator_badge,
+                fee_collector,
+            }
+            .instantiate();
+            component.globalize_with_access_rules(rules)
+        }
+
+        // Called by a new Barter from its constructor, once it is globalized. Returns the trade's short code,
+        // and the registrar badge the trade reports back with.
+
+        pub fn register_trade(
+            &mut self,
+            component: ComponentAddress,
+            creator: Option<NonFungibleGlobalId>,
+            offered: Vec<ResourceAddress>,
+            wanted: Vec<ResourceAddress>,
+        ) -> (String, Bucket) {
+            assert_blueprint(component, "Barter");
+            assert!(
+                self.trades.get(&component).is_none(),
+                "Trade {:?} is already registered",
+                component
+            );
+
+            let trade_hub: ComponentAddress =
+                borrow_component!(component).call("hub", scrypto_args!());
+            assert!(
+                trade_hub == Runtime::global_address(),
+                "Trade {:?} was created against another hub",
+                component
+            );
+
+            let short_code = self.register_short_code(component);
+
+            self.trades.insert(
+                component,
+                TradeListing {
+                    component,
+                    creator,
+                    offered,
+                    wanted,
+                    status: TradeStatus::Open,
+                    short_code: short_code.clone(),
+                },
+            );
+
+            self.trade_index.insert(self.trade_count, component);
+            self.trade_count += 1;
+
+            self.live_index.insert(self.live_count, component);
+            self.live_positions.insert(component, Some(self.live_count));
+            self.live_count += 1;
+
+            let registrar: Bucket = self.registrar_minter.authorize(|| {
+                borrow_resource_manager!(self.registrar_badge).mint(1)
+            });
+
+            (short_code, registrar)
+        }
+
+        // a badge identifying the same creator across all of their trades
+
+        pub fn register_creator(&mut self) -> Bucket {
+            self.registrar_minter.authorize(|| {
+                borrow_resource_manager!(self.creator_badge).mint_uuid_non_fungible(CreatorBadge {})
+            })
+        }
+
+        pub fn record_status(&mut self, component: ComponentAddress, status: TradeStatus) {
+            let short_code = {
+                let mut listing = self
+                    .trades
+                    .get_mut(&component)
+                    .expect("Trade is not registered with this hub");
+                listing.status = status;
+                listing.short_code.clone()
+            };
+
+            if matches!(
+                status,
+                TradeStatus::Settled | TradeStatus::Cancelled | TradeStatus::Expired
+            ) {
+                self.short_codes.insert(short_code, None);
+                self.remove_live(component);
+            }
+        }
+
+        pub fn record_terms(
+            &mut self,
+            component: ComponentAddress,
+            offered: Vec<ResourceAddress>,
+            wanted: Vec<ResourceAddress>,
+        ) {
+            let mut listing = self
+                .trades
+                .get_mut(&component)
+                .expect("Trade is not registered with this hub");
+            listing.offered = offered;
+            listing.wanted = wanted;
+        }
+
+        pub fn resolve_short_code(&self, short_code: String) -> Option<ComponentAddress> {
+            self.short_codes
+                .get(&short_code.to_uppercase())
+                .and_then(|component| *component)
+        }
+
+        pub fn trade(&self, component: ComponentAddress) -> Option<TradeListing> {
+            self.trades.get(&component).map(|listing| listing.clone())
+        }
+
+        // Every filter is optional - pass None to skip it. With no status filter only open trades are returned.
+
+        // Reads at most `limit` entries of the index from `start` and returns the matches among them, along with
+        // the start of the next page if there is one. Open and Filled trades are searched in the live index,
+        // other statuses in the index of every trade. The live index is reordered as trades finish, so a trade
+        // can move between pages while they are being read.
+
+        pub fn find_trades(
+            &self,
+            offered: Option<ResourceAddress>,
+            wanted: Option<ResourceAddress>,
+            creator: Option<NonFungibleGlobalId>,
+            status: Option<TradeStatus>,
+            start: u64,
+            limit: u32,
+        ) -> (Vec<TradeListing>, Option<u64>) {
+            let status = status.unwrap_or(TradeStatus::Open);
+
+            let (index, count) = match status {
+                TradeStatus::Open | TradeStatus::Filled => (&self.live_index, self.live_count),
+                _ => (&self.trade_index, self.trade_count),
+            };
+
+            let end = count.min(start.saturating_add(limit as u64));
+
+            let mut found: Vec<TradeListing> = Vec::new();
+
+            for position in start..end {
+                let component = *index.get(&position).unwrap();
+                let listing = self.trades.get(&component).unwrap();
+
+                if listing.status != status {
+                    continue;
+                }
+                if let Some(resource_address) = &offered {
+                    if !listing.offered.contains(resource_address) {
+                        continue;
+                    }
+                }
+                if let Some(resource_address) = &wanted {
+                    if !listing.wanted.contains(resource_address) {
+                        continue;
+                    }
+                }
+                if let Some(badge) = &creator {
+                    if listing.creator.as_ref() != Some(badge) {
+                        continue;
+                    }
+                }
+
+                found.push(listing.clone());
+            }
+
+            let next = if end < count { Some(end) } else { None };
+
+            (found, next)
+        }
+
+        pub fn fee_collector(&self) -> ComponentAddress {
+            self.fee_collector
+        }
+
+        pub fn registrar_badge(&self) -> ResourceAddress {
+            self.registrar_badge
+        }
+
+        pub fn creator_badge(&self) -> ResourceAddress {
+            self.creator_badge
+        }
+
+        pub fn trade_count(&self) -> u64 {
+            self.trade_count
+        }
+
+        pub fn live_count(&self) -> u64 {
+            self.live_count
+        }
+
+        // The last live trade takes the finished trade's position, so the index stays without gaps. Entries
+        // can't be removed from a KeyValueStore - the stale one past the end is overwritten by the next trade.
+
+        fn remove_live(&mut self, component: ComponentAddress) {
+            let position = match self.live_positions.get(&component).and_then(|position| *position) {
+                Some(position) => position,
+                None => return,
+            };
+
+            let last = self.live_count - 1;
+            if position != last {
+                let moved = *self.live_index.get(&last).unwrap();
+                self.live_index.insert(position, moved);
+                self.live_positions.insert(moved, Some(position));
+            }
+
+            self.live_positions.insert(component, None);
+            self.live_count = last;
+        }
+
+        // Starts from the shortest code and adds a character from the address hash until it finds one that
+        // isn't held by a live trade.
+
+        fn register_short_code(&mut self, component: ComponentAddress) -> String {
+            let encoded = crockford_base32(
+                hash(scrypto_encode(&component).unwrap()).as_ref(),
+            );
+
+            for length in SHORT_CODE_LENGTH..=encoded.len() {
+                let candidate = encoded[..length].to_string();
+
+                let taken = match self.short_codes.get(&candidate) {
+                    Some(holder) => holder.is_some(),
+                    None => false,
+                };
+
+                if !taken {
+                    self.short_codes.insert(candidate.clone(), Some(component));
+                    return candidate;
+                }
+            }
+
+            panic!("No free short code for {:?}", component);
+        }
+    }
+}
+
+const SHORT_CODE_LENGTH: usize = 6;
+
+// Crockford's base32 - no I, L, O or U, so codes read aloud or typed by hand are hard to get wrong
+
+const SHORT_CODE_ALPHABET: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+fn crockford_base32(bytes: &[u8]) -> String {
+    let mut code = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits: u32 = 0;
+
+    for byte in bytes.iter() {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+
+        while bits >= 5 {
+            bits -= 5;
+            code.push(SHORT_CODE_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+
+    code
+}
+
+#[derive(ScryptoSbor, Clone, Debug)]
+pub struct TradeListing {
+    pub component: ComponentAddress,
+    // the creator badge the trade was proposed with, if any
+    pub creator: Option<NonFungibleGlobalId>,
+    pub offered: Vec<ResourceAddress>,
+    pub wanted: Vec<ResourceAddress>,
+    pub status: TradeStatus,
+    pub short_code: String,
+}
+
+#[derive(NonFungibleData, ScryptoSbor)]
+pub struct CreatorBadge {}
@@ -0,0 +1,327 @@
+use crate::matching::{nfts_per_collection, AllowedIds, CollectionWant};
+use crate::predicates::nft_fields;
+use scrypto::prelude::*;
+
+// PROPOSAL VALIDATION
+
+// Checks run on a trade proposal before the Barter component creates any resources. A malformed proposal
+// would otherwise reach the ledger as a trade that can never be filled, or one that fills on terms nobody
+// meant - so each check panics with the reason the proposal was rejected.
+
+pub fn validate_proposal(
+    a_nft_buckets: &[Bucket],
+    a_token_buckets: &[Bucket],
+    b_nft_deposits: &[NonFungibleGlobalId],
+    b_nft_wants: &[CollectionWant],
+    b_token_deposits: &HashMap<ResourceAddress, Decimal>,
+) {
+    // A's side is what cancel and B's claim hand back, so a trade offering nothing could never close
+
+    assert!(
+        !(a_nft_buckets.is_empty() && a_token_buckets.is_empty()),
+        "Proposal offers nothing - A's side can't be empty"
+    );
+
+    let offered_resources = validate_offered(a_nft_buckets, a_token_buckets);
+
+    validate_ask(
+        &offered_resources,
+        b_nft_deposits,
+        b_nft_wants,
+        b_token_deposits,
+    );
+}
+
+// A's side - returns the resources offered, which the ask may not include
+
+pub fn validate_offered(
+    a_nft_buckets: &[Bucket],
+    a_token_buckets: &[Bucket],
+) -> HashSet<ResourceAddress> {
+    let mut offered_resources: HashSet<ResourceAddress> = HashSet::new();
+
+    for bucket in a_nft_buckets.iter() {
+        assert!(
+            is_non_fungible_resource(bucket.resource_address()),
+            "Fungible resource {:?} was passed in the NFT list",
+            bucket.resource_address()
+        );
+        assert!(
+            !bucket.is_empty(),
+            "Offered NFT bucket of {:?} is empty",
+            bucket.resource_address()
+        );
+        offered_resources.insert(bucket.resource_address());
+    }
+
+    for bucket in a_token_buckets.iter() {
+        assert!(
+            !is_non_fungible_resource(bucket.resource_address()),
+            "Non-fungible resource {:?} was passed in the token list",
+            bucket.resource_address()
+        );
+        assert!(
+            bucket.amount() > Decimal::zero(),
+            "Offered token bucket of {:?} is empty",
+            bucket.resource_address()
+        );
+        offered_resources.insert(bucket.resource_address());
+    }
+
+    offered_resources
+}
+
+// B's side, checked against what A offers - also used when A amends the ask on an open trade
+
+pub fn validate_ask(
+    offered_resources: &HashSet<ResourceAddress>,
+    b_nft_deposits: &[NonFungibleGlobalId],
+    b_nft_wants: &[CollectionWant],
+    b_token_deposits: &HashMap<ResourceAddress, Decimal>,
+) {
+    let mut asked_nfts: BTreeSet<&NonFungibleGlobalId> = BTreeSet::new();
+
+    for nft in b_nft_deposits.iter() {
+        assert!(asked_nfts.insert(nft), "NFT {:?} is asked for more than once", nft);
+        assert!(
+            is_non_fungible_resource(nft.resource_address()),
+            "Asked NFT {:?} is not from a non-fungible resource",
+            nft
+        );
+        assert!(
+            !offered_resources.contains(&nft.resource_address()),
+            "Proposal asks for NFT {:?} from a resource it is also offering",
+            nft
+        );
+    }
+
+    for want in b_nft_wants.iter() {
+        validate_want(want);
+        assert!(
+            !offered_resources.contains(&want.resource),
+            "Proposal wants NFTs from {:?}, which it is also offering",
+            want.resource
+        );
+    }
+
+    for (resource_address, amount) in b_token_deposits.iter() {
+        assert!(
+            *amount > Decimal::zero(),
+            "Asked amount of {:?} must be greater than zero, got {}",
+            resource_address,
+            amount
+        );
+        assert!(
+            !is_non_fungible_resource(*resource_address),
+            "Asked token {:?} is non-fungible, ask for it by NFT id instead",
+            resource_address
+        );
+        assert!(
+            !offered_resources.contains(resource_address),
+            "Proposal asks for {:?}, which it is also offering",
+            resource_address
+        );
+    }
+}
+
+// a want has to be possible to fill - enough allowed ids for its count, and a range that isn't backwards
+
+fn validate_want(want: &CollectionWant) {
+    assert!(
+        want.count > 0,
+        "Want for {:?} must ask for at least one NFT",
+        want.resource
+    );
+    assert!(
+        is_non_fungible_resource(want.resource),
+        "Wanted collection {:?} is not a non-fungible resource",
+        want.resource
+    );
+
+    let available: Option<u64> = match &want.allowed {
+        None => None,
+        Some(AllowedIds::Set(ids)) => {
+            let unique: BTreeSet<&NonFungibleLocalId> = ids.iter().collect();
+            assert!(
+                unique.len() == ids.len(),
+                "Allowed ids for {:?} list the same id more than once",
+                want.resource
+            );
+            Some(ids.len() as u64)
+        }
+        Some(AllowedIds::IntegerRange(from, to)) => {
+            assert!(
+                from <= to,
+                "Allowed id range for {:?} ends before it starts",
+                want.resource
+            );
+            // a range over every u64 holds more ids than a u64 can count, and more than any count can ask for
+            (to - from).checked_add(1)
+        }
+    };
+
+    if let Some(sample) = sample_nft(want) {
+        let fields = nft_fields(&sample);
+        for predicate in want.predicates.iter() {
+            if let Err(reason) = predicate.check_fields(&fields) {
+                panic!("Predicate on {:?} can never match: {}", want.resource, reason);
+            }
+        }
+    }
+
+    if let Some(available) = available {
+        assert!(
+            want.count as u64 <= available,
+            "Want for {:?} asks for {} NFTs but only allows {} ids",
+            want.resource,
+            want.count,
+            available
+        );
+    }
+}
+
+// Predicates name their field by position, and a collection's data can only be read from one of its NFTs, so
+// they are checked against a sample - the first allowed id, if it has been minted. A want without allowed ids
+// has no NFT to sample and its predicates are only checked against what is delivered.
+
+fn sample_nft(want: &CollectionWant) -> Option<NonFungibleGlobalId> {
+    let id = match &want.allowed {
+        Some(AllowedIds::Set(ids)) => ids.first()?.clone(),
+        Some(AllowedIds::IntegerRange(from, _)) => NonFungibleLocalId::integer(*from),
+        None => return None,
+    };
+
+    if borrow_resource_manager!(want.resource).non_fungible_exists(&id) {
+        Some(NonFungibleGlobalId::new(want.resource, id))
+    } else {
+        None
+    }
+}
+
+// a component the trade relies on must be running this package's code rather than a look-alike
+
+pub fn assert_blueprint(component: ComponentAddress, blueprint_name: &str) {
+    let component_ref = borrow_component!(component);
+    assert!(
+        component_ref.package_address() == Runtime::package_address()
+            && component_ref.blueprint_name() == blueprint_name,
+        "{:?} is not a {} from this package",
+        component,
+        blueprint_name
+    );
+}
+
+// deposits are sent back to a refund account without its owner signing, so it has to be an account
+
+pub fn assert_account(component: ComponentAddress) {
+    assert!(
+        matches!(
+            component,
+            ComponentAddress::Account(_)
+                | ComponentAddress::EcdsaSecp256k1VirtualAccount(_)
+                | ComponentAddress::EddsaEd25519VirtualAccount(_)
+        ),
+        "{:?} is not an account",
+        component
+    );
+}
+
+pub fn is_non_fungible_resource(resource_address: ResourceAddress) -> bool {
+    matches!(
+        borrow_resource_manager!(resource_address).resource_type(),
+        ResourceType::NonFungible { .. }
+    )
+}
+
+// TRADE SIZE LIMITS
+
+// Every vault on a side is emptied in a single call when the trade settles or is cancelled, so a side with too
+// many resources or NFTs could cost more than one transaction allows and leave its assets stuck for good.
+// Limits apply to each side separately. A proposal may tighten them but never raise them above the ceiling.
+
+// The ceiling is provisional - the numbers below are conservative guesses, not measurements.
+// tests/limits.rs checks that a side filled to every limit at once can still be cancelled, and settled with a
+// protocol fee and a royalty on every collection, under the normal cost unit limit. The cost at each size is
+// recorded with
+//     cargo test --release --test bench -- --ignored --nocapture
+// into bench_output.txt and bench_layout_output.txt.
+// Measured cost units at the ceiling: not yet recorded. Copy the figures here, next to the constant, and
+// set the ceiling from them before it is raised.
+
+#[derive(ScryptoSbor, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TradeLimits {
+    pub max_resources: u32,
+    pub max_nfts: u32,
+    pub max_fungibles: u32,
+}
+
+impl TradeLimits {
+    pub const CEILING: TradeLimits = TradeLimits {
+        max_resources: 20,
+        max_nfts: 50,
+        max_fungibles: 10,
+    };
+
+    pub fn assert_within_ceiling(&self) {
+        assert!(
+            self.max_resources <= Self::CEILING.max_resources
+                && self.max_nfts <= Self::CEILING.max_nfts
+                && self.max_fungibles <= Self::CEILING.max_fungibles,
+            "Trade limits can't be raised above {:?}",
+            Self::CEILING
+        );
+    }
+
+    pub fn check_side(
+        &self,
+        side: &str,
+        nfts: &[NonFungibleGlobalId],
+        tokens: &HashMap<ResourceAddress, Decimal>,
+    ) {
+        self.check_wanted_side(side, nfts, &[], tokens);
+    }
+
+    // as check_side, with collection wants counted at the number of NFTs they ask for
+
+    pub fn check_wanted_side(
+        &self,
+        side: &str,
+        nfts: &[NonFungibleGlobalId],
+        wants: &[CollectionWant],
+        tokens: &HashMap<ResourceAddress, Decimal>,
+    ) {
+        let per_collection = nfts_per_collection(nfts, wants);
+        let nft_count: usize = per_collection.values().sum();
+
+        let mut resources: HashSet<ResourceAddress> = per_collection.keys().cloned().collect();
+        resources.extend(tokens.keys().cloned());
+
+        assert!(
+            resources.len() as u32 <= self.max_resources,
+            "{} side holds {} distinct resources, the limit is {}",
+            side,
+            resources.len(),
+            self.max_resources
+        );
+        assert!(
+            nft_count as u32 <= self.max_nfts,
+            "{} side holds {} NFTs, the limit is {}",
+            side,
+            nft_count,
+            self.max_nfts
+        );
+        assert!(
+            tokens.len() as u32 <= self.max_fungibles,
+            "{} side holds {} fungible resources, the limit is {}",
+            side,
+            tokens.len(),
+            self.max_fungibles
+        );
+    }
+}
+
+impl Default for TradeLimits {
+    fn default() -> Self {
+        Self::CEILING
+    }
+}
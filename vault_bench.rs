@@ -0,0 +1,64 @@
+use crate::vault_store::VaultStore;
+use scrypto::prelude::*;
+
+// VAULT LAYOUT BENCHMARK
+
+// Holds a set of assets in one of the two layouts Barter has used for a side - every vault in a HashMap in the
+// component state, as the original blueprint did, or in a VaultStore. tests/bench.rs puts the same assets in
+// each and compares what a call touching one vault and a call emptying every vault cost. Only used by the bench.
+
+#[blueprint]
+mod vault_bench {
+
+    struct VaultBench {
+        layout: VaultLayout,
+        map_vaults: HashMap<ResourceAddress, Vault>,
+        store_vaults: VaultStore,
+    }
+
+    impl VaultBench {
+        pub fn instantiate_vault_bench(buckets: Vec<Bucket>, layout: VaultLayout) -> ComponentAddress {
+            let mut component = Self {
+                layout,
+                map_vaults: HashMap::new(),
+                store_vaults: VaultStore::new(),
+            };
+
+            for bucket in buckets.into_iter() {
+                component.put(bucket);
+            }
+
+            component.instantiate().globalize()
+        }
+
+        // touches the one vault for the bucket's resource, creating it if needed
+
+        pub fn put(&mut self, bucket: Bucket) {
+            match self.layout {
+                VaultLayout::HashMap => self
+                    .map_vaults
+                    .entry(bucket.resource_address())
+                    .or_insert(Vault::new(bucket.resource_address()))
+                    .put(bucket),
+                VaultLayout::VaultStore => self.store_vaults.put(bucket),
+            }
+        }
+
+        pub fn take_all(&mut self) -> Vec<Bucket> {
+            match self.layout {
+                VaultLayout::HashMap => self
+                    .map_vaults
+                    .values_mut()
+                    .map(|vault| vault.take_all())
+                    .collect(),
+                VaultLayout::VaultStore => self.store_vaults.take_all(),
+            }
+        }
+    }
+}
+
+#[derive(ScryptoSbor, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VaultLayout {
+    HashMap,
+    VaultStore,
+}
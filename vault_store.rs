@@ -0,0 +1,146 @@
+use scrypto::prelude::*;
+
+// VAULT STORAGE
+
+// Holds one vault per resource in a KeyValueStore, so a call only loads the vaults it actually touches rather
+// than every vault in the trade. The KeyValueStore can't be iterated, so the resource addresses are kept in a
+// separate index for the methods that need to empty every vault. A resource stays in the index only while its
+// vault holds something, so the index never lists more than the store actually holds.
+
+// How many distinct resources a trade can hold with this store, compared to holding every vault in a HashMap in
+// the component, has not been recorded yet. bench_vault_layouts in tests/bench.rs holds the same assets in each
+// layout and measures both - the figures should be noted here once it has been run.
+
+#[derive(ScryptoSbor)]
+pub struct VaultStore {
+    vaults: KeyValueStore<ResourceAddress, Vault>,
+    resources: Vec<ResourceAddress>,
+}
+
+impl VaultStore {
+    pub fn new() -> Self {
+        Self {
+            vaults: KeyValueStore::new(),
+            resources: Vec::new(),
+        }
+    }
+
+    pub fn put(&mut self, bucket: Bucket) {
+        let resource_address = bucket.resource_address();
+
+        // vaults stay in the store once emptied, only the index entry is removed
+
+        if self.vaults.get(&resource_address).is_some() {
+            self.vaults.get_mut(&resource_address).unwrap().put(bucket);
+        } else {
+            self.vaults
+                .insert(resource_address, Vault::with_bucket(bucket));
+        }
+
+        if !self.resources.contains(&resource_address) {
+            self.resources.push(resource_address);
+        }
+    }
+
+    pub fn take(&mut self, resource_address: ResourceAddress, amount: Decimal) -> Bucket {
+        let bucket = self
+            .vaults
+            .get_mut(&resource_address)
+            .expect("No vault for this resource")
+            .take(amount);
+
+        self.unindex_if_empty(resource_address);
+        bucket
+    }
+
+    pub fn take_non_fungibles(
+        &mut self,
+        resource_address: ResourceAddress,
+        ids: &BTreeSet<NonFungibleLocalId>,
+    ) -> Bucket {
+        let bucket = self
+            .vaults
+            .get_mut(&resource_address)
+            .expect("No vault for this resource")
+            .take_non_fungibles(ids);
+
+        self.unindex_if_empty(resource_address);
+        bucket
+    }
+
+    pub fn take_all(&mut self) -> Vec<Bucket> {
+        let resources: Vec<ResourceAddress> = self.resources.drain(..).collect();
+
+        let mut buckets: Vec<Bucket> = vec![];
+
+        for resource_address in resources.into_iter() {
+            buckets.push(self.vaults.get_mut(&resource_address).unwrap().take_all())
+        }
+
+        buckets
+    }
+
+    // Empties only the selected vaults, so a store too large to empty in one transaction can be emptied over
+    // several. Whatever is left stays in the index until it is taken.
+
+    pub fn take_selected(&mut self, selection: &ClaimSelection) -> Vec<Bucket> {
+        let resources: Vec<ResourceAddress> = match selection {
+            ClaimSelection::Resources(requested) => {
+                for resource_address in requested.iter() {
+                    assert!(
+                        self.resources.contains(resource_address),
+                        "Nothing left to claim in {:?}",
+                        resource_address
+                    );
+                }
+                requested.clone()
+            }
+            ClaimSelection::First(count) => self
+                .resources
+                .iter()
+                .take(*count as usize)
+                .cloned()
+                .collect(),
+        };
+
+        self.resources
+            .retain(|resource_address| !resources.contains(resource_address));
+
+        let mut buckets: Vec<Bucket> = vec![];
+
+        for resource_address in resources.into_iter() {
+            buckets.push(self.vaults.get_mut(&resource_address).unwrap().take_all())
+        }
+
+        buckets
+    }
+
+    fn unindex_if_empty(&mut self, resource_address: ResourceAddress) {
+        if self.vaults.get(&resource_address).unwrap().is_empty() {
+            self.resources
+                .retain(|indexed| *indexed != resource_address);
+        }
+    }
+
+    pub fn resources(&self) -> &[ResourceAddress] {
+        &self.resources
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+}
+
+impl Default for VaultStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// which vaults a chunked claim should empty - a given list of resources, or the first N still held
+
+#[derive(ScryptoSbor, Clone, Debug, PartialEq, Eq)]
+pub enum ClaimSelection {
+    Resources(Vec<ResourceAddress>),
+    First(u32),
+}